@@ -95,10 +95,19 @@ fn cmdlet_service_main(
 Service!("cmdlet", cmdlet_service_main);
 
 fn main() {
+    // The log file of the service is configured by the service manifest.
+    ceviche::logging::set_setup(None);
+
     let service = CmdletService::load().expect("unable to load cmdlet service");
     let mut controller = Controller::new(service.get_service_name(),
         service.get_display_name(), service.get_description());
 
+    // The systemd unit runs the service from its working directory, like the cmdlet functions.
+    #[cfg(target_os = "linux")] {
+        controller.working_directory = service.get_working_dir()
+            .and_then(|path| path.to_str().map(|path| path.to_string()));
+    }
+
     if let Some(cmd) = env::args().nth(1) {
         match cmd.as_str() {
             "create" => {
@@ -121,6 +130,15 @@ fn main() {
                     println!("{}", e);
                 }
             }
+            #[cfg(target_os = "linux")]
+            "render" => {
+                match controller.render_systemd() {
+                    Ok(files) => for file in files {
+                        println!("# /{}\n{}", file.path.display(), file.content);
+                    },
+                    Err(e) => println!("{}", e),
+                }
+            }
             "run" => {
                 let (tx, rx) = mpsc::channel();
                 let _tx = tx.clone();
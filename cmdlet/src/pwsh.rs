@@ -1,5 +1,5 @@
 
-use std::path::{PathBuf};
+use std::path::{Path, PathBuf};
 use std::process::{Command};
 use serde::{Serialize, Deserialize};
 
@@ -30,38 +30,62 @@ pub fn encode_command(command: &str) -> String {
     base64::encode(command_bytes.as_slice())
 }
 
+/// Quotes a string literal for PowerShell, paths may contain spaces on every platform.
+pub fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Builds the command running `command` with PowerShell, without loading the user profile
+/// which may not exist for the account of the service.
+pub fn powershell_command(powershell: &Path, command: &str) -> Command {
+    let encoded_command = encode_command(command);
+    let mut powershell = Command::new(powershell);
+    powershell.arg("-NoLogo").arg("-NoProfile").arg("-NonInteractive")
+        .arg("-EncodedCommand").arg(encoded_command.as_str());
+    powershell
+}
+
 pub fn find_cmdlet_base(module_name: &str) -> Option<PathBuf> {
     let powershell = find_powershell()?;
 
     let command = format!(
         "Get-Module -Name {} -ListAvailable | Select-Object -First 1 | foreach {{ $_.ModuleBase }}",
-        module_name);
-
-    let encoded_command = encode_command(command.as_str());
+        quote_literal(module_name));
 
-    let output = Command::new(&powershell)
-        .arg("-EncodedCommand").arg(encoded_command.as_str())
-        .output().ok()?;
+    let output = powershell_command(&powershell, &command).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
 
     let module_base = String::from_utf8(output.stdout).ok()?;
-    return Some(PathBuf::from(module_base.trim()));
+    let module_base = module_base.trim();
+    if module_base.is_empty() {
+        return None;
+    }
+    Some(PathBuf::from(module_base))
 }
 
-pub fn get_module_manifest(module_name: &str) -> Option<PSModuleManifest> {
-    let powershell = find_powershell()?;
-    let manifest_path = find_cmdlet_base(module_name)?;
-    let manifest_path = manifest_path.as_path().to_str()?;
+pub fn get_module_manifest(module_name: &str) -> Result<PSModuleManifest, String> {
+    let powershell = find_powershell().ok_or("unable to find PowerShell")?;
+    let manifest_path = find_cmdlet_base(module_name)
+        .ok_or(format!("unable to find module {}", module_name))?
+        .join(format!("{}.psd1", module_name));
+    let manifest_path = manifest_path.as_path().to_str()
+        .ok_or(format!("invalid manifest path {}", manifest_path.display()))?;
 
     let command = format!(
-        "Import-PowerShellDataFile -Path \"{}\\{}.psd1\" | ConvertTo-Json",
-        manifest_path, module_name);
+        "Import-PowerShellDataFile -LiteralPath {} | ConvertTo-Json",
+        quote_literal(manifest_path));
 
-    let encoded_command = encode_command(command.as_str());
-
-    let output = Command::new(&powershell)
-        .arg("-EncodedCommand").arg(encoded_command.as_str())
-        .output().ok()?;
+    let output = powershell_command(&powershell, &command).output()
+        .map_err(|e| format!("unable to run PowerShell: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("unable to import {}: {}", manifest_path,
+            String::from_utf8_lossy(&output.stderr).trim()));
+    }
 
-    let json_output = String::from_utf8(output.stdout).ok()?;
-    serde_json::from_str(json_output.as_str()).ok()
+    let json_output = String::from_utf8(output.stdout)
+        .map_err(|e| format!("invalid output importing {}: {}", manifest_path, e))?;
+    serde_json::from_str(json_output.as_str())
+        .map_err(|e| format!("invalid manifest {}: {}", manifest_path, e))
 }
@@ -2,7 +2,6 @@
 use std::fs::File;
 use std::io::BufReader;
 use std::path::{PathBuf};
-use std::process::{Command};
 use serde::{Serialize, Deserialize};
 
 use crate::pwsh::*;
@@ -71,8 +70,8 @@ pub fn get_service_manifest() -> Option<ServiceManifest> {
 }
 
 impl CmdletService {
-    pub fn load() -> Option<Self> {
-        let service_manifest = get_service_manifest()?;
+    pub fn load() -> Result<Self, String> {
+        let service_manifest = get_service_manifest().ok_or("unable to load service manifest")?;
         let module_name = service_manifest.get_module_name().to_string();
         let module_manifest = get_module_manifest(&module_name)?;
     
@@ -85,7 +84,7 @@ impl CmdletService {
         let stop_command = service_manifest.stop_command.to_string();
         let log_file = service_manifest.log_file.unwrap_or(format!("{}.log", service_name.as_str()));
     
-        Some(CmdletService {
+        Ok(CmdletService {
             service_name: service_name.to_string(),
             display_name: display_name.to_string(),
             description: description.to_string(),
@@ -116,7 +115,7 @@ impl CmdletService {
     }
 
     pub fn get_description(&self) -> &str {
-        self.service_name.as_str()
+        self.description.as_str()
     }
 
     pub fn get_module_name(&self) -> &str {
@@ -156,12 +155,9 @@ fn run_cmdlet_function(service: &CmdletService, cmdlet: &str, function: &str) ->
 
     let command = format!(
         "Import-Module -Name {};\n\
-        {}", cmdlet, function);
-
-    let encoded_command = encode_command(command.as_str());
+        {}", quote_literal(cmdlet), function);
 
-    Command::new(&powershell)
-        .arg("-EncodedCommand").arg(encoded_command.as_str())
+    powershell_command(&powershell, &command)
         .current_dir(working_dir)
         .output()
 }
@@ -0,0 +1,214 @@
+#![cfg(target_os = "linux")]
+
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Stub of `pwsh` logging the decoded commands it runs, and answering the module lookup and manifest import,
+/// which fails for the `Broken` module.
+const PWSH_STUB: &str = r#"#!/bin/sh
+while [ $# -gt 0 ]; do
+    if [ "$1" = "-EncodedCommand" ]; then
+        command=$(printf '%s' "$2" | base64 -d | tr -d '\000')
+        shift
+    fi
+    shift
+done
+printf '%s\n' "$command" >> "$(dirname "$0")/pwsh.log"
+case "$command" in
+    Get-Module*"'Foo'"*)
+        echo "$(dirname "$0")/modules/Foo/1.0.0"
+        ;;
+    Get-Module*"'Broken'"*)
+        echo "$(dirname "$0")/modules/Broken/1.0.0"
+        ;;
+    Import-PowerShellDataFile*Broken.psd1*)
+        echo "Broken.psd1 is not a valid data file" >&2
+        exit 1
+        ;;
+    Import-PowerShellDataFile*)
+        echo '{"ModuleVersion": "1.0.0", "CompanyName": "Foo Inc.", "Description": "Foo module"}'
+        ;;
+esac
+"#;
+
+struct TestService {
+    dir: PathBuf,
+}
+
+impl TestService {
+    /// Installs the service executable with a manifest for `module_name` and a stub `pwsh` in a
+    /// directory named `name`, the stub being found on the PATH of the service.
+    fn new(name: &str, module_name: &str) -> TestService {
+        let dir = env::temp_dir().join(format!("cmdlet-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("bin")).unwrap();
+
+        let pwsh = dir.join("bin").join("pwsh");
+        fs::write(&pwsh, PWSH_STUB).unwrap();
+        fs::set_permissions(&pwsh, fs::Permissions::from_mode(0o755)).unwrap();
+
+        fs::copy(
+            env!("CARGO_BIN_EXE_cmdlet_service"),
+            dir.join("foo-service"),
+        )
+        .unwrap();
+        let manifest = format!(
+            r#"{{"ServiceName": "foo", "WorkingDir": "{}", "ModuleName": "{}", "StartCommand": "Start-Foo", "StopCommand": "Stop-Foo"}}"#,
+            dir.display(),
+            module_name
+        );
+        fs::write(dir.join("foo-service.service.json"), manifest).unwrap();
+
+        TestService { dir }
+    }
+
+    /// PATH of the service, with the stub first.
+    fn path(&self) -> String {
+        format!(
+            "{}:{}",
+            self.dir.join("bin").display(),
+            env::var("PATH").unwrap_or_default()
+        )
+    }
+
+    fn spawn(&self, command: &str) -> Child {
+        self.spawn_with_args(&[command])
+    }
+
+    fn spawn_with_args(&self, args: &[&str]) -> Child {
+        Command::new(self.dir.join("foo-service"))
+            .args(args)
+            .env("PATH", self.path())
+            .current_dir(&self.dir)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap()
+    }
+
+    fn output(&self, command: &str) -> Output {
+        Command::new(self.dir.join("foo-service"))
+            .arg(command)
+            .env("PATH", self.path())
+            .current_dir(&self.dir)
+            .output()
+            .unwrap()
+    }
+
+    fn pwsh_log(&self) -> String {
+        fs::read_to_string(self.dir.join("bin").join("pwsh.log")).unwrap_or_default()
+    }
+
+    fn wait_for_pwsh_log(&self, content: &str) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while Instant::now() < deadline {
+            if self.pwsh_log().contains(content) {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        false
+    }
+}
+
+impl Drop for TestService {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn wait_with_timeout(child: &mut Child) -> Option<i32> {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while Instant::now() < deadline {
+        if let Some(status) = child.try_wait().unwrap() {
+            return status.code();
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    let _ = child.kill();
+    None
+}
+
+#[test]
+fn imports_module_manifest_from_unix_path() {
+    let service = TestService::new("manifest", "Foo");
+    let mut child = service.spawn("invalid");
+    assert_eq!(wait_with_timeout(&mut child), Some(0));
+
+    let manifest_path = Path::new(&service.dir)
+        .join("bin")
+        .join("modules/Foo/1.0.0/Foo.psd1");
+    let log = service.pwsh_log();
+    assert!(log.contains("Get-Module -Name 'Foo' -ListAvailable"));
+    assert!(log.contains(&format!(
+        "Import-PowerShellDataFile -LiteralPath '{}'",
+        manifest_path.display()
+    )));
+}
+
+#[test]
+fn runs_start_and_stop_commands() {
+    let service = TestService::new("run", "Foo");
+    let mut child = service.spawn("run");
+    assert!(service.wait_for_pwsh_log("Start-Foo"));
+
+    unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGINT) };
+    assert_eq!(wait_with_timeout(&mut child), Some(0));
+
+    let log = service.pwsh_log();
+    assert!(log.contains("Import-Module -Name 'Foo';\nStart-Foo\n"));
+    assert!(log.contains("Import-Module -Name 'Foo';\nStop-Foo\n"));
+}
+
+#[test]
+fn renders_unit_from_service_manifest() {
+    let service = TestService::new("render", "Foo");
+    let output = service.output("render");
+    assert!(output.status.success());
+
+    let unit = String::from_utf8(output.stdout).unwrap();
+
+    assert!(unit.contains("# /lib/systemd/system/foo.service\n"));
+    assert!(unit.contains(&format!(
+        "ExecStart={}\n",
+        service.dir.join("foo-service").display()
+    )));
+    assert!(unit.contains(&format!("WorkingDirectory={}\n", service.dir.display())));
+}
+
+#[test]
+fn runs_start_and_stop_commands_in_service_mode() {
+    let service = TestService::new("service", "Foo");
+    let mut child = service.spawn_with_args(&[]);
+    assert!(service.wait_for_pwsh_log("Start-Foo"));
+
+    unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGTERM) };
+    assert_eq!(wait_with_timeout(&mut child), Some(0));
+
+    let log = service.pwsh_log();
+    assert!(log.contains("Import-Module -Name 'Foo';\nStart-Foo\n"));
+    assert!(log.contains("Import-Module -Name 'Foo';\nStop-Foo\n"));
+}
+
+#[test]
+fn fails_when_module_is_not_found() {
+    let service = TestService::new("missing", "Bar");
+    let mut child = service.spawn("run");
+    assert_ne!(wait_with_timeout(&mut child), Some(0));
+    assert!(!service.pwsh_log().contains("Import-PowerShellDataFile"));
+}
+
+#[test]
+fn reports_module_manifest_errors() {
+    let service = TestService::new("broken", "Broken");
+    let output = service.output("render");
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("Broken.psd1 is not a valid data file")
+    );
+}
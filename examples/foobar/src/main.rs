@@ -8,62 +8,19 @@ use std::sync::mpsc;
 use ceviche::controller::*;
 use ceviche::{Service, ServiceEvent};
 use clap::App;
-use log::LevelFilter;
-use log4rs::append::console::ConsoleAppender;
-use log4rs::append::file::FileAppender;
-use log4rs::config::{Appender, Config, Root};
-use log4rs::encode::pattern::PatternEncoder;
 
 static SERVICE_NAME: &'static str = "foobar";
 static DISPLAY_NAME: &'static str = "FooBar Service";
 static DESCRIPTION: &'static str = "This is the FooBar service";
 
-#[cfg(windows)]
-const LOG_PATH: &'static str = "C:\\Windows\\Temp\\foobar.log";
-#[cfg(any(unix, target_os = "macos"))]
-const LOG_PATH: &'static str = "/tmp/foobar.log";
-
 enum CustomServiceEvent {}
 
-fn init_logging(standalone_mode: bool) -> Option<()> {
-    if standalone_mode {
-        let stdout = ConsoleAppender::builder().build();
-
-        let config = Config::builder()
-            .appender(Appender::builder().build("stdout", Box::new(stdout)))
-            .build(Root::builder().appender("stdout").build(LevelFilter::Info))
-            .ok()?;
-
-        log4rs::init_config(config).ok()?;
-    } else {
-        let file_appender = FileAppender::builder()
-            .encoder(Box::new(PatternEncoder::new(
-                "{d(%Y-%m-%d %H:%M:%S)} {M} [{h({l})}] - {m}{n}",
-            )))
-            .build(LOG_PATH)
-            .ok()?;
-
-        let config = Config::builder()
-            .appender(Appender::builder().build("file_appender", Box::new(file_appender)))
-            .build(
-                Root::builder()
-                    .appender("file_appender")
-                    .build(LevelFilter::Info),
-            )
-            .ok()?;
-
-        log4rs::init_config(config).ok()?;
-    }
-    Some(())
-}
-
 fn my_service_main(
     rx: mpsc::Receiver<ServiceEvent<CustomServiceEvent>>,
     _tx: mpsc::Sender<ServiceEvent<CustomServiceEvent>>,
     args: Vec<String>,
-    standalone_mode: bool,
+    _standalone_mode: bool,
 ) -> u32 {
-    init_logging(standalone_mode);
     info!("foobar service started");
     info!("args: {:?}", args);
 
@@ -81,7 +38,7 @@ fn my_service_main(
     0
 }
 
-Service!("Foobar", my_service_main);
+Service!(SERVICE_NAME, my_service_main);
 
 fn main() {
     let yaml = load_yaml!("cli.yml");
@@ -113,6 +70,10 @@ fn main() {
             }
         }
         "standalone" => {
+            if let Err(e) = ceviche::logging::setup(SERVICE_NAME, true) {
+                println!("{}", e);
+            }
+
             let (tx, rx) = mpsc::channel();
             let _tx = tx.clone();
 
@@ -0,0 +1,362 @@
+//! Local control channel to send custom commands to a running service.
+//!
+//! The service listens on a Unix domain socket. Clients write one JSON command per line, which the
+//! service receives as a `ServiceEvent::Custom` event, and read one JSON reply per line: `{"ok":true}`,
+//! `{"ok":true,"data":...}` or `{"ok":false,"error":"..."}`. Services receiving `Request` events answer
+//! through the `Responder` of the request, other commands are acknowledged once delivered.
+//!
+//! The built-in `ControlEvent` commands, such as `{"event":"Pause"}`, are delivered as the matching
+//! `ServiceEvent` instead.
+//!
+//! Controllers with `control_socket` set listen on `default_path()` while the service runs, for services
+//! declared with `Service!(name, function, control)`. Other services keep any custom event type.
+//!
+//! ```rust,no_run
+//! mod plain {
+//!     use std::sync::mpsc;
+//!     use ceviche::controller::dispatch;
+//!     use ceviche::{Service, ServiceEvent};
+//!
+//!     // Not deserializable, only delivered from the service itself.
+//!     pub enum CustomServiceEvent {}
+//!
+//!     fn service_main(_rx: mpsc::Receiver<ServiceEvent<CustomServiceEvent>>, _tx: mpsc::Sender<ServiceEvent<CustomServiceEvent>>, _args: Vec<String>, _standalone_mode: bool) -> u32 { 0 }
+//!
+//!     Service!("plain", service_main);
+//! }
+//!
+//! mod controlled {
+//!     use std::sync::mpsc;
+//!     use ceviche::controller::dispatch_with_control;
+//!     use ceviche::{Service, ServiceEvent};
+//!
+//!     #[derive(serde::Deserialize)]
+//!     pub enum Command { Reload }
+//!
+//!     fn service_main(_rx: mpsc::Receiver<ServiceEvent<Command>>, _tx: mpsc::Sender<ServiceEvent<Command>>, _args: Vec<String>, _standalone_mode: bool) -> u32 { 0 }
+//!
+//!     Service!("controlled", service_main, control);
+//! }
+//! ```
+
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use log::debug;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::controller::set_paused;
+use crate::socket::bind_private;
+use crate::Error;
+use crate::ServiceEvent;
+
+/// Commands handled by the control socket itself.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event")]
+pub enum ControlEvent {
+    /// Sends `ServiceEvent::Pause` unless the service is already paused.
+    Pause,
+    /// Sends `ServiceEvent::Continue` if the service is paused.
+    Continue,
+}
+
+/// How long a client waits for the service to answer a `Request`.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Reply sent for each command received on the control socket.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Reply {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Reply {
+    fn success(data: Option<serde_json::Value>) -> Reply {
+        Reply {
+            ok: true,
+            data,
+            error: None,
+        }
+    }
+
+    fn failure(error: &str) -> Reply {
+        Reply {
+            ok: false,
+            data: None,
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+/// Answers a command received on the control socket. The command is acknowledged with `{"ok":true}`
+/// when the responder is dropped without replying.
+#[derive(Debug)]
+pub struct Responder {
+    tx: mpsc::Sender<Reply>,
+}
+
+impl Responder {
+    /// Replies with `data`.
+    pub fn reply<R: Serialize + ?Sized>(self, data: &R) {
+        let reply = match serde_json::to_value(data) {
+            Ok(data) => Reply::success(Some(data)),
+            Err(e) => Reply::failure(&format!("Failed to serialize reply: {}", e)),
+        };
+        let _ = self.tx.send(reply);
+    }
+
+    /// Rejects the command with `error`.
+    pub fn fail(self, error: &str) {
+        let _ = self.tx.send(Reply::failure(error));
+    }
+}
+
+/// A command received on the control socket, with the responder its reply is sent with.
+///
+/// ```rust
+/// use std::sync::mpsc;
+/// use std::thread;
+/// use ceviche::control::{request, ControlSocket, Request};
+/// use ceviche::ServiceEvent;
+///
+/// let path = std::env::temp_dir().join(format!("ceviche-doctest-request-{}.sock", std::process::id()));
+/// let (tx, rx) = mpsc::channel::<ServiceEvent<Request<String>>>();
+/// ControlSocket::bind(&path).unwrap().forward_events(tx);
+///
+/// thread::spawn(move || {
+///     while let Ok(ServiceEvent::Custom(request)) = rx.recv() {
+///         match request.command.as_str() {
+///             "status" => request.responder.reply(&["running", "healthy"]),
+///             _ => request.responder.fail("Unknown command"),
+///         }
+///     }
+/// });
+///
+/// let status: Vec<String> = request(&path, "status").unwrap();
+/// assert_eq!(status, vec!["running", "healthy"]);
+/// assert_eq!(request::<_, ()>(&path, "restart").unwrap_err().message, "Unknown command");
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Request<C> {
+    pub command: C,
+    pub responder: Responder,
+}
+
+/// Custom events the control socket delivers: commands deserialized from JSON, or `Request` events
+/// carrying a command and its responder.
+pub trait ControlCommand: Sized {
+    fn from_control(line: &str, responder: Responder) -> Result<Self, serde_json::Error>;
+}
+
+impl<T: DeserializeOwned> ControlCommand for T {
+    fn from_control(line: &str, _responder: Responder) -> Result<T, serde_json::Error> {
+        serde_json::from_str(line)
+    }
+}
+
+impl<C: DeserializeOwned> ControlCommand for Request<C> {
+    fn from_control(line: &str, responder: Responder) -> Result<Request<C>, serde_json::Error> {
+        Ok(Request {
+            command: serde_json::from_str(line)?,
+            responder,
+        })
+    }
+}
+
+/// Default path of the control socket of a service, in its runtime directory on Linux.
+pub fn default_path(service_name: &str) -> PathBuf {
+    if cfg!(target_os = "macos") {
+        Path::new("/var/run/").join(format!("{}.sock", service_name))
+    } else {
+        Path::new("/run/")
+            .join(service_name)
+            .join(format!("{}.sock", service_name))
+    }
+}
+
+/// Control socket of a running service, only accessible by the user the service runs as.
+///
+/// ```rust
+/// use std::sync::mpsc;
+/// use ceviche::control::{send_command, ControlEvent, ControlSocket};
+/// use ceviche::ServiceEvent;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Debug, PartialEq, Serialize, Deserialize)]
+/// enum Command {
+///     Reload,
+///     SetLevel(String),
+/// }
+///
+/// let path = std::env::temp_dir().join(format!("ceviche-doctest-{}.sock", std::process::id()));
+/// let (tx, rx) = mpsc::channel();
+/// ControlSocket::bind(&path).unwrap().forward_events::<Command>(tx);
+///
+/// send_command(&path, &Command::SetLevel("debug".to_string())).unwrap();
+/// match rx.recv().unwrap() {
+///     ServiceEvent::Custom(command) => assert_eq!(command, Command::SetLevel("debug".to_string())),
+///     _ => panic!("unexpected event"),
+/// }
+/// assert!(send_command(&path, &"Restart").is_err());
+///
+/// send_command(&path, &ControlEvent::Pause).unwrap();
+/// assert!(matches!(rx.recv().unwrap(), ServiceEvent::Pause));
+/// assert!(ceviche::controller::is_paused());
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+pub struct ControlSocket {
+    path: PathBuf,
+    listener: UnixListener,
+}
+
+impl ControlSocket {
+    /// Listens on `path`, replacing a socket left behind by a service that is not running anymore.
+    pub fn bind(path: &Path) -> Result<ControlSocket, Error> {
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(Error::new(&format!(
+                    "Control socket {} is already in use",
+                    path.display()
+                )));
+            }
+            fs::remove_file(path)
+                .map_err(|e| Error::new(&format!("Failed to delete {}: {}", path.display(), e)))?;
+        }
+
+        Ok(ControlSocket {
+            path: path.to_path_buf(),
+            listener: bind_private(path)?,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Sends the commands received on the socket to `tx` as `ServiceEvent::Custom` events, from a
+    /// background thread serving each client on its own thread.
+    pub fn forward_events<T: ControlCommand + Send + 'static>(
+        self,
+        tx: mpsc::Sender<ServiceEvent<T>>,
+    ) {
+        thread::spawn(move || {
+            for stream in self.listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let tx = tx.clone();
+                        thread::spawn(move || serve_client(stream, tx));
+                    }
+                    Err(e) => debug!("Failed to accept control client: {}", e),
+                }
+            }
+        });
+    }
+}
+
+fn parse_event<T: ControlCommand>(
+    line: &str,
+    responder: Responder,
+) -> Result<Option<ServiceEvent<T>>, serde_json::Error> {
+    match serde_json::from_str::<ControlEvent>(line) {
+        Ok(ControlEvent::Pause) => Ok(set_paused(true).then_some(ServiceEvent::Pause)),
+        Ok(ControlEvent::Continue) => Ok(set_paused(false).then_some(ServiceEvent::Continue)),
+        Err(_) => {
+            T::from_control(line, responder).map(|command| Some(ServiceEvent::Custom(command)))
+        }
+    }
+}
+
+fn serve_client<T: ControlCommand>(stream: UnixStream, tx: mpsc::Sender<ServiceEvent<T>>) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(e) => {
+            debug!("Failed to serve control client: {}", e);
+            return;
+        }
+    };
+
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                debug!("Failed to read from control client: {}", e);
+                break;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (reply_tx, reply_rx) = mpsc::channel();
+        let reply = match parse_event::<T>(&line, Responder { tx: reply_tx }) {
+            Ok(event) => match event.map_or(Ok(()), |event| tx.send(event)) {
+                Ok(_) => match reply_rx.recv_timeout(REPLY_TIMEOUT) {
+                    Ok(reply) => reply,
+                    Err(mpsc::RecvTimeoutError::Disconnected) => Reply::success(None),
+                    Err(mpsc::RecvTimeoutError::Timeout) => {
+                        Reply::failure("The service did not reply")
+                    }
+                },
+                Err(_) => Reply::failure("The service is stopping"),
+            },
+            Err(e) => Reply::failure(&format!("Invalid command: {}", e)),
+        };
+
+        let reply = serde_json::to_string(&reply).unwrap_or_default();
+        if writeln!(writer, "{}", reply).is_err() {
+            break;
+        }
+    }
+}
+
+/// Sends `command` to the service listening on the control socket at `path` and returns the data of its
+/// reply, `null` when the service did not reply with data.
+pub fn request<C: Serialize + ?Sized, R: DeserializeOwned>(
+    path: &Path,
+    command: &C,
+) -> Result<R, Error> {
+    let io_error = |e: std::io::Error| {
+        Error::new(&format!(
+            "Failed to send command to {}: {}",
+            path.display(),
+            e
+        ))
+    };
+
+    let command = serde_json::to_string(command)
+        .map_err(|e| Error::new(&format!("Failed to serialize command: {}", e)))?;
+    let mut stream = UnixStream::connect(path).map_err(io_error)?;
+    writeln!(stream, "{}", command).map_err(io_error)?;
+
+    let mut reply = String::new();
+    BufReader::new(stream)
+        .read_line(&mut reply)
+        .map_err(io_error)?;
+    let reply: Reply = serde_json::from_str(&reply)
+        .map_err(|e| Error::new(&format!("Invalid reply from {}: {}", path.display(), e)))?;
+
+    if !reply.ok {
+        return Err(Error::new(
+            &reply
+                .error
+                .unwrap_or_else(|| "The command was rejected".to_string()),
+        ));
+    }
+    serde_json::from_value(reply.data.unwrap_or_default())
+        .map_err(|e| Error::new(&format!("Invalid reply from {}: {}", path.display(), e)))
+}
+
+/// Sends `command` to the service listening on the control socket at `path` and waits for its reply.
+pub fn send_command<C: Serialize + ?Sized>(path: &Path, command: &C) -> Result<(), Error> {
+    request::<C, serde_json::Value>(path, command).map(|_| ())
+}
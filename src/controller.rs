@@ -1,3 +1,4 @@
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc;
 
 use crate::Error;
@@ -11,15 +12,31 @@ cfg_if! {
         pub use self::windows::dispatch;
     } else if #[cfg(target_os = "macos")] {
         mod macos;
+        mod pause;
         pub use self::macos::MacosController as Controller;
         pub use self::macos::Session as Session;
         pub use self::macos::dispatch;
+        #[cfg(feature = "control")]
+        pub use self::macos::dispatch_with_control;
         pub use self::macos::LaunchAgentTargetSesssion;
     } else if #[cfg(target_os = "linux")] {
+        mod daemon;
+        mod detect;
         mod linux;
+        mod openrc;
+        mod pause;
+        mod supervise;
+        mod sysv;
         pub use self::linux::LinuxController as Controller;
+        pub use self::daemon::DaemonController;
+        pub use self::detect::{DetectedController, InitSystem, StandaloneController};
+        pub use self::openrc::OpenRcController;
+        pub use self::supervise::{RunitController, S6Controller};
+        pub use self::sysv::SysvController;
         pub use self::linux::Session as Session;
         pub use self::linux::dispatch;
+        #[cfg(feature = "control")]
+        pub use self::linux::dispatch_with_control;
     } else {
         mod dummy;
         pub use self::dummy::DummyController as Controller;
@@ -38,6 +55,106 @@ pub type ServiceMainFn<T> = fn(
     standalone_mode: bool,
 ) -> u32;
 
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+/// Whether the service is paused, following the `Pause` and `Continue` events delivered by `dispatch`.
+pub fn is_paused() -> bool {
+    PAUSED.load(Ordering::SeqCst)
+}
+
+/// Updates the paused state, returning whether it changed.
+pub(crate) fn set_paused(paused: bool) -> bool {
+    PAUSED.swap(paused, Ordering::SeqCst) != paused
+}
+
+#[cfg(target_os = "linux")]
+static REOPEN_LOGS: AtomicBool = AtomicBool::new(false);
+
+/// Makes `dispatch` reopen the log files on `SIGHUP` instead of stopping the service, for services
+/// writing to a log file rotated by logrotate.
+#[cfg(target_os = "linux")]
+pub(crate) fn set_reopen_logs(reopen_logs: bool) {
+    REOPEN_LOGS.store(reopen_logs, Ordering::SeqCst);
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn reopen_logs() -> bool {
+    REOPEN_LOGS.load(Ordering::SeqCst)
+}
+
+#[cfg(all(feature = "metrics", target_os = "linux"))]
+static UNIT_NAME: std::sync::Mutex<Option<String>> = std::sync::Mutex::new(None);
+
+/// Sets the name of the systemd units of the service, `<service_name>@<instance>` for an instance of a
+/// template, which `dispatch` reads the restart count of the service from.
+#[cfg(all(feature = "metrics", target_os = "linux"))]
+pub(crate) fn set_unit_name(unit_name: String) {
+    if let Ok(mut current) = UNIT_NAME.lock() {
+        *current = Some(unit_name);
+    }
+}
+
+#[cfg(all(feature = "metrics", target_os = "linux"))]
+pub(crate) fn unit_name() -> Option<String> {
+    UNIT_NAME
+        .lock()
+        .ok()
+        .and_then(|unit_name| unit_name.clone())
+}
+
+#[cfg(all(feature = "control", any(target_os = "linux", target_os = "macos")))]
+static CONTROL_SOCKET: std::sync::Mutex<Option<std::path::PathBuf>> = std::sync::Mutex::new(None);
+
+/// Makes `dispatch` listen on the control socket at `path` while the service runs.
+#[cfg(all(feature = "control", any(target_os = "linux", target_os = "macos")))]
+pub(crate) fn set_control_socket(path: Option<std::path::PathBuf>) {
+    if let Ok(mut current) = CONTROL_SOCKET.lock() {
+        *current = path;
+    }
+}
+
+#[cfg(all(feature = "control", any(target_os = "linux", target_os = "macos")))]
+pub(crate) fn control_socket() -> Option<std::path::PathBuf> {
+    CONTROL_SOCKET.lock().ok().and_then(|path| path.clone())
+}
+
+/// Environment variable holding the instance name of a templated service, set by its unit from `%I`.
+pub const INSTANCE_ENV: &str = "CEVICHE_INSTANCE";
+
+/// Instance name of a templated service, from an `--instance <name>` or `--instance=<name>` argument or
+/// from the `CEVICHE_INSTANCE` environment variable.
+///
+/// ```rust
+/// use ceviche::controller::instance_name;
+///
+/// let args: Vec<String> = ["gateway", "--instance", "eu"].iter().map(|arg| arg.to_string()).collect();
+/// assert_eq!(instance_name(&args), Some("eu".to_string()));
+/// let args = vec!["gateway".to_string(), "--instance=us".to_string()];
+/// assert_eq!(instance_name(&args), Some("us".to_string()));
+/// ```
+pub fn instance_name(args: &[String]) -> Option<String> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--instance" {
+            return args.next().cloned();
+        }
+        if let Some(instance) = arg.strip_prefix("--instance=") {
+            return Some(instance.to_string());
+        }
+    }
+
+    std::env::var(INSTANCE_ENV)
+        .ok()
+        .filter(|instance| !instance.is_empty())
+}
+
+/// State of an installed service.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ServiceStatus {
+    Running,
+    Stopped,
+}
+
 /// Controllers implement this interface. They also need to implement the `register()` method; because the signature
 /// of service_main_wrapper depends on the system the method is not part of the interface.
 pub trait ControllerInterface {
@@ -49,6 +166,18 @@ pub trait ControllerInterface {
     fn start(&mut self) -> Result<(), Error>;
     /// Stops the service.
     fn stop(&mut self) -> Result<(), Error>;
+    /// Queries whether the service is running.
+    fn status(&mut self) -> Result<ServiceStatus, Error> {
+        Err(Error::new("Querying the service status is not supported"))
+    }
+    /// Pauses the service, which receives `ServiceEvent::Pause`.
+    fn pause(&mut self) -> Result<(), Error> {
+        Err(Error::new("Pausing the service is not supported"))
+    }
+    /// Resumes the paused service, which receives `ServiceEvent::Continue`.
+    fn resume(&mut self) -> Result<(), Error> {
+        Err(Error::new("Resuming the service is not supported"))
+    }
     cfg_if! {
         if #[cfg(target_os = "macos")] {
             /// Loads the agent service.
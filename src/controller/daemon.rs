@@ -0,0 +1,363 @@
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::info;
+
+use super::linux::{current_exe, LinuxServiceMainWrapperFn};
+use super::pause::set_pause_signals;
+use crate::controller::{ControllerInterface, ServiceStatus};
+use crate::pidfile::PidFile;
+use crate::Error;
+
+fn os_error(message: &str) -> Error {
+    Error::new(&format!("{}: {}", message, io::Error::last_os_error()))
+}
+
+/// Opens `path`, creating it when missing, and makes `fd` refer to it.
+fn redirect_fd(fd: libc::c_int, path: &Path, write: bool) -> Result<(), Error> {
+    let file = OpenOptions::new()
+        .read(!write)
+        .append(write)
+        .create(write)
+        .mode(0o640)
+        .open(path)
+        .map_err(|e| Error::new(&format!("Failed to open {}: {}", path.display(), e)))?;
+    match unsafe { libc::dup2(file.as_raw_fd(), fd) } {
+        -1 => Err(os_error(&format!(
+            "Failed to redirect to {}",
+            path.display()
+        ))),
+        _ => Ok(()),
+    }
+}
+
+fn process_exists(pid: libc::pid_t) -> bool {
+    match unsafe { libc::kill(pid, 0) } {
+        0 => true,
+        _ => io::Error::last_os_error().raw_os_error() == Some(libc::EPERM),
+    }
+}
+
+/// Forks the daemon, only returning in the daemon process. The original process exits once the
+/// daemon reported whether it locked `pid_file`, which is what `Type=forking` units expect.
+pub(super) fn daemonize(
+    pid_file: &Path,
+    working_directory: &Path,
+    stdout_path: Option<&Path>,
+    stderr_path: Option<&Path>,
+) -> Result<PidFile, Error> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err(os_error("Failed to create pipe"));
+    }
+    let (read_fd, write_fd) = (fds[0], fds[1]);
+
+    match unsafe { libc::fork() } {
+        -1 => return Err(os_error("Failed to fork")),
+        0 => unsafe {
+            libc::close(read_fd);
+        },
+        _ => {
+            unsafe { libc::close(write_fd) };
+            let mut report = Vec::new();
+            let _ = unsafe { File::from_raw_fd(read_fd) }.read_to_end(&mut report);
+            if report == [0] {
+                process::exit(0);
+            }
+            match report.is_empty() {
+                true => eprintln!("Failed to start the daemon"),
+                false => eprintln!("{}", String::from_utf8_lossy(&report)),
+            }
+            process::exit(1);
+        }
+    }
+
+    let mut pipe = unsafe { File::from_raw_fd(write_fd) };
+    match detach(pid_file, working_directory, stdout_path, stderr_path) {
+        Ok(pid_file) => {
+            let _ = pipe.write_all(&[0]);
+            Ok(pid_file)
+        }
+        Err(e) => {
+            let _ = pipe.write_all(e.message.as_bytes());
+            unsafe { libc::_exit(1) }
+        }
+    }
+}
+
+fn detach(
+    pid_file: &Path,
+    working_directory: &Path,
+    stdout_path: Option<&Path>,
+    stderr_path: Option<&Path>,
+) -> Result<PidFile, Error> {
+    if unsafe { libc::setsid() } == -1 {
+        return Err(os_error("Failed to create session"));
+    }
+
+    match unsafe { libc::fork() } {
+        -1 => return Err(os_error("Failed to fork")),
+        0 => (),
+        _ => unsafe { libc::_exit(0) },
+    }
+
+    env::set_current_dir(working_directory).map_err(|e| {
+        Error::new(&format!(
+            "Failed to change directory to {}: {}",
+            working_directory.display(),
+            e
+        ))
+    })?;
+    unsafe { libc::umask(0o022) };
+
+    let pid_file = PidFile::acquire(pid_file)?;
+
+    let dev_null = Path::new("/dev/null");
+    redirect_fd(libc::STDIN_FILENO, dev_null, false)?;
+    redirect_fd(libc::STDOUT_FILENO, stdout_path.unwrap_or(dev_null), true)?;
+    redirect_fd(libc::STDERR_FILENO, stderr_path.unwrap_or(dev_null), true)?;
+
+    Ok(pid_file)
+}
+
+/// Runs the service as a traditional daemon, for hosts without a service manager.
+///
+/// `register()` detaches the process from its terminal with a double fork and records the pid of the
+/// daemon in a locked pid file, `start()` launches the executable and `stop()` signals the pid found in
+/// the pid file. The process that launched the daemon only exits once the pid file is locked, with a
+/// non-zero exit code when the daemon failed to start.
+pub struct DaemonController {
+    pub service_name: String,
+    pub display_name: String,
+    pub description: String,
+    /// Arguments `start()` launches the executable with, which must make it call `register()`.
+    pub arguments: Vec<String>,
+    /// File holding the pid of the daemon, defaults to `/run/<service_name>/<service_name>.pid`.
+    pub pid_file: PathBuf,
+    /// Directory the daemon runs in, defaults to `/`.
+    pub working_directory: PathBuf,
+    /// File the standard output of the daemon is appended to, defaults to `/dev/null`.
+    pub stdout_path: Option<PathBuf>,
+    /// File the standard error of the daemon is appended to, defaults to `/dev/null`.
+    pub stderr_path: Option<PathBuf>,
+    /// Time `stop()` waits for the daemon to exit.
+    pub stop_timeout: Duration,
+    /// Signals delivering `ServiceEvent::Pause` and `ServiceEvent::Continue`, `SIGUSR1` and `SIGUSR2` by
+    /// default, which leaves `SIGTSTP` (Ctrl-Z) to the terminal.
+    pub pause_signals: Option<(i32, i32)>,
+}
+
+impl DaemonController {
+    pub fn new(service_name: &str, display_name: &str, description: &str) -> DaemonController {
+        DaemonController {
+            service_name: service_name.to_string(),
+            display_name: display_name.to_string(),
+            description: description.to_string(),
+            arguments: Vec::new(),
+            pid_file: PidFile::default_path(service_name),
+            working_directory: PathBuf::from("/"),
+            stdout_path: None,
+            stderr_path: None,
+            stop_timeout: Duration::from_secs(10),
+            pause_signals: Some((libc::SIGUSR1, libc::SIGUSR2)),
+        }
+    }
+
+    pub fn register(
+        &mut self,
+        service_main_wrapper: LinuxServiceMainWrapperFn,
+    ) -> Result<(), Error> {
+        let _pid_file = daemonize(
+            &self.pid_file,
+            &self.working_directory,
+            self.stdout_path.as_deref(),
+            self.stderr_path.as_deref(),
+        )?;
+        set_pause_signals(self.pause_signals);
+        service_main_wrapper(env::args().collect());
+        Ok(())
+    }
+
+    fn running_pid(&self) -> Result<Option<libc::pid_t>, Error> {
+        Ok(PidFile::read(&self.pid_file)?.map(|pid| pid as libc::pid_t))
+    }
+
+    fn signal(&self, signal: Option<i32>) -> Result<(), Error> {
+        let signal = signal.ok_or("Pause signals are disabled")?;
+        let pid = self
+            .running_pid()?
+            .ok_or_else(|| Error::new(&format!("{} is not running", self.service_name)))?;
+
+        if unsafe { libc::kill(pid, signal) } != 0 {
+            return Err(os_error(&format!("Failed to signal pid {}", pid)));
+        }
+        Ok(())
+    }
+}
+
+impl ControllerInterface for DaemonController {
+    fn create(&mut self) -> Result<(), Error> {
+        info!(
+            "{} runs as a daemon, there is nothing to install",
+            self.service_name
+        );
+        Ok(())
+    }
+
+    fn delete(&mut self) -> Result<(), Error> {
+        info!(
+            "{} runs as a daemon, there is nothing to uninstall",
+            self.service_name
+        );
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<(), Error> {
+        if let Some(pid) = self.running_pid()? {
+            return Err(Error::new(&format!(
+                "{} is already running with pid {}",
+                self.service_name, pid
+            )));
+        }
+
+        let program = current_exe()?;
+        let status = Command::new(&program)
+            .args(&self.arguments)
+            .status()
+            .map_err(|e| Error::new(&format!("Failed to execute {}: {}", program, e)))?;
+        match status.success() {
+            true => Ok(()),
+            false => Err(Error::new(&format!(
+                "Failed to start {}",
+                self.service_name
+            ))),
+        }
+    }
+
+    fn stop(&mut self) -> Result<(), Error> {
+        let pid = match self.running_pid()? {
+            Some(pid) => pid,
+            None => {
+                info!("{} is not running", self.service_name);
+                return Ok(());
+            }
+        };
+
+        if unsafe { libc::kill(pid, libc::SIGTERM) } != 0 {
+            return Err(os_error(&format!("Failed to signal pid {}", pid)));
+        }
+
+        let start = Instant::now();
+        while process_exists(pid) {
+            if start.elapsed() > self.stop_timeout {
+                return Err(Error::new(&format!(
+                    "{} did not stop within {} seconds",
+                    self.service_name,
+                    self.stop_timeout.as_secs()
+                )));
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+
+        Ok(())
+    }
+
+    fn status(&mut self) -> Result<ServiceStatus, Error> {
+        match self.running_pid()? {
+            Some(_) => Ok(ServiceStatus::Running),
+            None => Ok(ServiceStatus::Stopped),
+        }
+    }
+
+    fn pause(&mut self) -> Result<(), Error> {
+        self.signal(self.pause_signals.map(|(pause, _)| pause))
+    }
+
+    fn resume(&mut self) -> Result<(), Error> {
+        self.signal(self.pause_signals.map(|(_, resume)| resume))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    const CHILD_ENV: &str = "CEVICHE_DAEMON_TEST";
+
+    /// Daemonizes with the pid file in the directory named in `CHILD_ENV`, reports the pid of the daemon
+    /// in `daemon.out`, its standard output, and holds the pid file until it is terminated. Does nothing
+    /// when run by the test harness itself.
+    #[test]
+    fn child_daemon() {
+        let dir = match env::var(CHILD_ENV) {
+            Ok(dir) => PathBuf::from(dir),
+            Err(_) => return,
+        };
+        let _pid_file = daemonize(
+            &dir.join("daemon.pid"),
+            &dir,
+            Some(&dir.join("daemon.out")),
+            None,
+        )
+        .unwrap();
+        println!("{}", process::id());
+        thread::sleep(Duration::from_secs(10));
+        process::exit(0);
+    }
+
+    #[test]
+    fn pid_file_holds_daemon_pid() {
+        let dir = env::temp_dir().join(format!("ceviche-daemon-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let pid_path = dir.join("daemon.pid");
+
+        let mut child = Command::new(env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "controller::daemon::tests::child_daemon",
+                "--nocapture",
+                "-q",
+            ])
+            .env(CHILD_ENV, &dir)
+            .spawn()
+            .unwrap();
+        // The launching process exits once the daemon locked the pid file.
+        let status = child.wait().unwrap();
+        assert!(status.success());
+
+        let pid = PidFile::read(&pid_path).unwrap().unwrap();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let reported = loop {
+            let output = fs::read_to_string(dir.join("daemon.out")).unwrap_or_default();
+            if let Ok(pid) = output.trim().parse::<u32>() {
+                break pid;
+            }
+            assert!(
+                Instant::now() < deadline,
+                "the daemon did not report its pid"
+            );
+            thread::sleep(Duration::from_millis(50));
+        };
+        assert_eq!(pid, reported);
+        assert_ne!(pid, child.id());
+        assert!(PidFile::acquire(&pid_path).is_err());
+
+        unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) };
+        while process_exists(pid as libc::pid_t) {
+            assert!(Instant::now() < deadline, "the daemon did not exit");
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert_eq!(PidFile::read(&pid_path).unwrap(), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,293 @@
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use log::{info, warn};
+
+use super::linux::{current_exe, LinuxController, LinuxServiceMainWrapperFn};
+use super::openrc::OpenRcController;
+use super::supervise::{RunitController, S6Controller};
+use super::sysv::SysvController;
+use crate::controller::{ControllerInterface, ServiceStatus};
+use crate::Error;
+
+/// Init systems the service can be installed with on Linux.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InitSystem {
+    Systemd,
+    OpenRc,
+    Runit,
+    S6,
+    Sysv,
+    /// No init system manages services, as in most containers: the service runs in the foreground.
+    Standalone,
+}
+
+impl InitSystem {
+    /// Detects the init system the host was booted with.
+    pub fn detect() -> InitSystem {
+        if Path::new("/run/systemd/system").is_dir() {
+            InitSystem::Systemd
+        } else if Path::new("/run/openrc").is_dir() {
+            InitSystem::OpenRc
+        } else if Path::new("/run/runit").is_dir() {
+            InitSystem::Runit
+        } else if Path::new("/run/s6").is_dir()
+            || Path::new("/run/service/.s6-svscan").is_dir()
+            || Path::new("/service/.s6-svscan").is_dir()
+        {
+            InitSystem::S6
+        } else if Path::new("/etc/init.d").is_dir()
+            && init_process_name().as_deref() == Some("init")
+        {
+            InitSystem::Sysv
+        } else {
+            InitSystem::Standalone
+        }
+    }
+}
+
+fn init_process_name() -> Option<String> {
+    fs::read_to_string("/proc/1/comm")
+        .ok()
+        .map(|comm| comm.trim().to_string())
+}
+
+impl fmt::Display for InitSystem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            InitSystem::Systemd => "systemd",
+            InitSystem::OpenRc => "openrc",
+            InitSystem::Runit => "runit",
+            InitSystem::S6 => "s6",
+            InitSystem::Sysv => "sysv",
+            InitSystem::Standalone => "standalone",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Parses the name of an init system, as printed by `Display`.
+///
+/// ```rust
+/// use ceviche::controller::InitSystem;
+///
+/// assert_eq!("OpenRC".parse::<InitSystem>().unwrap(), InitSystem::OpenRc);
+/// assert_eq!(InitSystem::Runit.to_string().parse::<InitSystem>().unwrap(), InitSystem::Runit);
+/// assert!("upstart".parse::<InitSystem>().is_err());
+/// ```
+impl FromStr for InitSystem {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<InitSystem, Error> {
+        match name.to_lowercase().as_str() {
+            "systemd" => Ok(InitSystem::Systemd),
+            "openrc" => Ok(InitSystem::OpenRc),
+            "runit" => Ok(InitSystem::Runit),
+            "s6" => Ok(InitSystem::S6),
+            "sysv" | "sysvinit" => Ok(InitSystem::Sysv),
+            "standalone" | "none" => Ok(InitSystem::Standalone),
+            _ => Err(Error::new(&format!("Unknown init system {}", name))),
+        }
+    }
+}
+
+/// Controller used when no init system manages services: every operation fails with an explanation,
+/// and the service can only be run in the foreground.
+pub struct StandaloneController {
+    pub service_name: String,
+}
+
+impl StandaloneController {
+    pub fn new(
+        service_name: &str,
+        _display_name: &str,
+        _description: &str,
+    ) -> StandaloneController {
+        StandaloneController {
+            service_name: service_name.to_string(),
+        }
+    }
+
+    pub fn register(
+        &mut self,
+        service_main_wrapper: LinuxServiceMainWrapperFn,
+    ) -> Result<(), Error> {
+        service_main_wrapper(env::args().collect());
+        Ok(())
+    }
+
+    fn unsupported(&self, operation: &str) -> Error {
+        let executable = current_exe().unwrap_or_else(|_| self.service_name.to_string());
+        Error::new(&format!(
+            "Cannot {} {}: no init system was detected, run {} in the foreground instead",
+            operation, self.service_name, executable
+        ))
+    }
+}
+
+impl ControllerInterface for StandaloneController {
+    fn create(&mut self) -> Result<(), Error> {
+        Err(self.unsupported("create"))
+    }
+
+    fn delete(&mut self) -> Result<(), Error> {
+        Err(self.unsupported("delete"))
+    }
+
+    fn start(&mut self) -> Result<(), Error> {
+        Err(self.unsupported("start"))
+    }
+
+    fn stop(&mut self) -> Result<(), Error> {
+        Err(self.unsupported("stop"))
+    }
+}
+
+/// Controller of an init system, returned by `LinuxController::detect()`. It implements
+/// `ControllerInterface` and `register()` by delegating to the concrete controller, which can be matched
+/// on to configure the settings of its init system.
+///
+/// ```rust
+/// use ceviche::controller::{Controller, DetectedController, InitSystem};
+///
+/// let mut controller = Controller::with_init_system(InitSystem::Sysv, "foobar", "FooBar", "FooBar service");
+/// if let DetectedController::Sysv(ref mut controller) = controller {
+///     controller.user = Some("foobar".to_string());
+/// }
+/// assert_eq!(controller.init_system(), InitSystem::Sysv);
+/// ```
+pub enum DetectedController {
+    Systemd(Box<LinuxController>),
+    OpenRc(OpenRcController),
+    Runit(RunitController),
+    S6(S6Controller),
+    Sysv(SysvController),
+    Standalone(StandaloneController),
+}
+
+impl DetectedController {
+    pub fn init_system(&self) -> InitSystem {
+        match self {
+            DetectedController::Systemd(_) => InitSystem::Systemd,
+            DetectedController::OpenRc(_) => InitSystem::OpenRc,
+            DetectedController::Runit(_) => InitSystem::Runit,
+            DetectedController::S6(_) => InitSystem::S6,
+            DetectedController::Sysv(_) => InitSystem::Sysv,
+            DetectedController::Standalone(_) => InitSystem::Standalone,
+        }
+    }
+
+    /// Register the `service_main_wrapper` function, this function is generated by the `Service!` macro.
+    pub fn register(
+        &mut self,
+        service_main_wrapper: LinuxServiceMainWrapperFn,
+    ) -> Result<(), Error> {
+        match self {
+            DetectedController::Systemd(controller) => controller.register(service_main_wrapper),
+            DetectedController::OpenRc(controller) => controller.register(service_main_wrapper),
+            DetectedController::Runit(controller) => controller.register(service_main_wrapper),
+            DetectedController::S6(controller) => controller.register(service_main_wrapper),
+            DetectedController::Sysv(controller) => controller.register(service_main_wrapper),
+            DetectedController::Standalone(controller) => controller.register(service_main_wrapper),
+        }
+    }
+
+    fn controller(&mut self) -> &mut dyn ControllerInterface {
+        match self {
+            DetectedController::Systemd(controller) => &mut **controller,
+            DetectedController::OpenRc(controller) => controller,
+            DetectedController::Runit(controller) => controller,
+            DetectedController::S6(controller) => controller,
+            DetectedController::Sysv(controller) => controller,
+            DetectedController::Standalone(controller) => controller,
+        }
+    }
+}
+
+impl ControllerInterface for DetectedController {
+    fn create(&mut self) -> Result<(), Error> {
+        self.controller().create()
+    }
+
+    fn delete(&mut self) -> Result<(), Error> {
+        self.controller().delete()
+    }
+
+    fn start(&mut self) -> Result<(), Error> {
+        self.controller().start()
+    }
+
+    fn stop(&mut self) -> Result<(), Error> {
+        self.controller().stop()
+    }
+
+    fn status(&mut self) -> Result<ServiceStatus, Error> {
+        self.controller().status()
+    }
+
+    fn pause(&mut self) -> Result<(), Error> {
+        self.controller().pause()
+    }
+
+    fn resume(&mut self) -> Result<(), Error> {
+        self.controller().resume()
+    }
+}
+
+impl LinuxController {
+    /// Returns the controller of the init system the host was booted with, falling back to the
+    /// standalone controller when there is none.
+    pub fn detect(service_name: &str, display_name: &str, description: &str) -> DetectedController {
+        let init_system = InitSystem::detect();
+        match init_system {
+            InitSystem::Standalone => warn!(
+                "No init system detected, {} can only run in the foreground",
+                service_name
+            ),
+            _ => info!("Detected init system {}", init_system),
+        }
+        LinuxController::with_init_system(init_system, service_name, display_name, description)
+    }
+
+    /// Returns the controller of `init_system`, to override detection.
+    pub fn with_init_system(
+        init_system: InitSystem,
+        service_name: &str,
+        display_name: &str,
+        description: &str,
+    ) -> DetectedController {
+        match init_system {
+            InitSystem::Systemd => DetectedController::Systemd(Box::new(LinuxController::new(
+                service_name,
+                display_name,
+                description,
+            ))),
+            InitSystem::OpenRc => DetectedController::OpenRc(OpenRcController::new(
+                service_name,
+                display_name,
+                description,
+            )),
+            InitSystem::Runit => DetectedController::Runit(RunitController::new(
+                service_name,
+                display_name,
+                description,
+            )),
+            InitSystem::S6 => {
+                DetectedController::S6(S6Controller::new(service_name, display_name, description))
+            }
+            InitSystem::Sysv => DetectedController::Sysv(SysvController::new(
+                service_name,
+                display_name,
+                description,
+            )),
+            InitSystem::Standalone => DetectedController::Standalone(StandaloneController::new(
+                service_name,
+                display_name,
+                description,
+            )),
+        }
+    }
+}
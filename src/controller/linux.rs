@@ -2,14 +2,34 @@ use std::env;
 use std::fs::{self, File};
 use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{self, Command};
 use std::sync::mpsc;
+use std::time::Duration;
 
 use ctrlc;
-use log::{debug, info};
-
-use crate::controller::{ControllerInterface, ServiceMainFn};
+use log::{debug, info, warn};
+
+use super::daemon::daemonize;
+use super::pause::{forward_pause_signals, set_pause_signals};
+#[cfg(feature = "control")]
+use crate::control::{self, ControlEvent};
+#[cfg(feature = "control")]
+use crate::controller::{control_socket, set_control_socket};
+use crate::controller::{
+    instance_name, reopen_logs, set_reopen_logs, ControllerInterface, ServiceMainFn, ServiceStatus,
+};
+#[cfg(feature = "metrics")]
+use crate::controller::{set_unit_name, unit_name};
+use crate::definition::{ServiceDefinition, ServiceFile};
+use crate::dependency::Dependency;
+use crate::logging::{self, RotationPolicy};
+#[cfg(feature = "metrics")]
+use crate::metrics;
+use crate::pidfile::PidFile;
+use crate::restart::RestartPolicy;
+use crate::schedule::Schedule;
 use crate::session;
+use crate::systemd::{escape_unit_name, sysusers_snippet, Hardening};
 use crate::Error;
 use crate::ServiceEvent;
 
@@ -19,21 +39,22 @@ use {
     systemd_rs::login::session as login_session,
 };
 
-type LinuxServiceMainWrapperFn = extern "system" fn(args: Vec<String>);
+pub(super) type LinuxServiceMainWrapperFn = fn(args: Vec<String>) -> u32;
 pub type Session = session::Session_<String>;
 
-fn systemctl_execute(args: &[&str]) -> Result<(), Error> {
-    let mut process = Command::new("systemctl");
+pub(super) fn command_execute(program: &str, args: &[&str]) -> Result<(), Error> {
+    let mut process = Command::new(program);
     process.args(args);
 
+    let name = args.first().unwrap_or(&program);
     let output = process
         .output()
-        .map_err(|e| Error::new(&format!("Failed to execute command {}: {}", args[0], e)))?;
+        .map_err(|e| Error::new(&format!("Failed to execute command {}: {}", name, e)))?;
 
     if !output.status.success() {
         return Err(Error::new(&format!(
             "Command \"{}\" failed ({}): {}",
-            args[0],
+            name,
             output.status.code().expect("Process terminated by signal"),
             std::str::from_utf8(&output.stderr).unwrap_or_default()
         )));
@@ -46,6 +67,65 @@ fn systemctl_execute(args: &[&str]) -> Result<(), Error> {
     Ok(())
 }
 
+/// Runs a command and returns its exit code, for commands reporting a state through it.
+pub(super) fn command_exit_code(program: &str, args: &[&str]) -> Result<i32, Error> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| Error::new(&format!("Failed to execute command {}: {}", program, e)))?;
+
+    output
+        .status
+        .code()
+        .ok_or_else(|| Error::new(&format!("Command {} terminated by signal", program)))
+}
+
+/// Returns whether `program` is found in `PATH` or the system binary directories.
+pub(super) fn program_exists(program: &str) -> bool {
+    let path = env::var("PATH").unwrap_or_default();
+    let found = path
+        .split(':')
+        .chain(["/usr/sbin", "/sbin"])
+        .any(|dir| Path::new(dir).join(program).is_file());
+    found
+}
+
+/// Path of the running executable, used as the command of the installed service.
+pub(super) fn current_exe() -> Result<String, Error> {
+    let exe_path = fs::read_link("/proc/self/exe")
+        .map_err(|e| Error::new(&format!("Failed to read /proc/self/exe: {}", e)))?;
+    exe_path
+        .to_str()
+        .map(|path| path.to_string())
+        .ok_or_else(|| Error::new("Failed to parse /proc/self/exe"))
+}
+
+/// Quotes `value` for a POSIX shell script.
+pub(super) fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Fails when a name of `environment` is not a valid shell variable name, which `export` would reject or
+/// interpret.
+pub(super) fn check_environment_names(environment: &[(String, String)]) -> Result<(), Error> {
+    for (name, _) in environment {
+        let valid = name.chars().enumerate().all(|(index, c)| {
+            c == '_' || c.is_ascii_alphabetic() || (index > 0 && c.is_ascii_digit())
+        });
+        if !valid || name.is_empty() {
+            return Err(Error::new(&format!(
+                "Invalid environment variable name \"{}\"",
+                name
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn systemctl_execute(args: &[&str]) -> Result<(), Error> {
+    command_execute("systemctl", args)
+}
+
 fn systemd_install_daemon(name: &str) -> Result<(), Error> {
     systemctl_execute(&["daemon-reload"])?;
     systemctl_execute(&["enable", name])
@@ -71,11 +151,82 @@ fn systemd_stop_daemon(name: &str) -> Result<(), Error> {
     systemctl_execute(&["stop", name])
 }
 
+fn systemd_kill_daemon(name: &str, signal: i32) -> Result<(), Error> {
+    systemctl_execute(&[
+        "kill",
+        "--kill-whom=main",
+        &format!("--signal={}", signal),
+        name,
+    ])
+}
+
+fn systemd_status_daemon(name: &str) -> Result<ServiceStatus, Error> {
+    match command_exit_code("systemctl", &["is-active", "--quiet", name])? {
+        0 => Ok(ServiceStatus::Running),
+        _ => Ok(ServiceStatus::Stopped),
+    }
+}
+
+fn systemd_create_sysusers(path: &Path) -> Result<(), Error> {
+    command_execute(
+        "systemd-sysusers",
+        &[path.to_str().ok_or("Failed to parse sysusers.d path")?],
+    )
+}
+
 pub struct LinuxController {
     pub service_name: String,
     pub display_name: String,
     pub description: String,
     pub config: Option<String>,
+    /// Working directory of the service (`WorkingDirectory=`), `/` when unset.
+    pub working_directory: Option<String>,
+    /// Environment variables set for the service (`Environment=`).
+    pub environment: Vec<(String, String)>,
+    /// Files the environment is read from (`EnvironmentFile=`), prefix a path with `-` to ignore it when missing.
+    pub environment_files: Vec<String>,
+    /// User the service runs as (`User=`), the service runs as root when unset.
+    pub user: Option<String>,
+    /// Group the service runs as (`Group=`), defaults to the primary group of `user`.
+    pub group: Option<String>,
+    /// Additional groups of the service process (`SupplementaryGroups=`).
+    pub supplementary_groups: Vec<String>,
+    /// Runs the service as a transient user allocated by systemd (`DynamicUser=yes`).
+    pub dynamic_user: bool,
+    /// Creates `user` as a system user through a sysusers.d snippet when the service is created.
+    pub sysusers: bool,
+    /// Sandboxing directives added to the unit.
+    pub hardening: Hardening,
+    /// Restart policy of the service, the service is never restarted by default.
+    pub restart: RestartPolicy,
+    /// Services and targets the service depends on (`Requires=`, `Wants=`, `After=` and `Before=`).
+    pub dependencies: Vec<Dependency>,
+    /// Forks into the background when started, the unit then uses `Type=forking` with a `PIDFile=`.
+    pub daemonize: bool,
+    /// Pid file locked by the service while it runs, defaults to `/run/<service_name>/<service_name>.pid`
+    /// with `daemonize`. The unit creates the directory of a pid file under `/run` with `RuntimeDirectory=`.
+    pub pid_file: Option<PathBuf>,
+    /// Log file of the service, rotated by logrotate with `log_rotation` through a snippet written to
+    /// `/etc/logrotate.d/<service_name>` when the service is created.
+    pub log_file: Option<PathBuf>,
+    pub log_rotation: RotationPolicy,
+    /// Signals delivering `ServiceEvent::Pause` and `ServiceEvent::Continue`, `SIGUSR1` and `SIGUSR2` by
+    /// default, which leaves `SIGTSTP` (Ctrl-Z) to the terminal. When unset, `pause()` and `resume()` go
+    /// through the control socket if `control_socket` is set, and fail otherwise.
+    pub pause_signals: Option<(i32, i32)>,
+    /// Listens on the control socket at `control::default_path()` while the service runs, delivering the
+    /// commands sent with `send_command()` to the service. The service must be declared with
+    /// `Service!(name, function, control)`, whose custom events are deserialized from the commands.
+    #[cfg(feature = "control")]
+    pub control_socket: bool,
+    /// Runs the service periodically from a `.timer` unit, the service then runs once and exits with the
+    /// return code of the service main function. The timer is enabled, started and stopped in place of
+    /// the service.
+    pub schedule: Option<Schedule>,
+    /// Instance of a templated service: the controller installs the template unit `<service_name>@.service`
+    /// and manages the `<service_name>@<instance>.service` unit. When the service runs, `register()` reads
+    /// the instance from the arguments or from the environment set by the unit, see `instance_name()`.
+    pub instance: Option<String>,
 }
 
 impl LinuxController {
@@ -85,6 +236,26 @@ impl LinuxController {
             display_name: display_name.to_string(),
             description: description.to_string(),
             config: None,
+            working_directory: None,
+            environment: Vec::new(),
+            environment_files: Vec::new(),
+            user: None,
+            group: None,
+            supplementary_groups: Vec::new(),
+            dynamic_user: false,
+            sysusers: false,
+            hardening: Hardening::default(),
+            restart: RestartPolicy::default(),
+            dependencies: Vec::new(),
+            daemonize: false,
+            pid_file: None,
+            log_file: None,
+            log_rotation: RotationPolicy::interval(Duration::from_secs(86400)),
+            pause_signals: Some((libc::SIGUSR1, libc::SIGUSR2)),
+            #[cfg(feature = "control")]
+            control_socket: false,
+            schedule: None,
+            instance: None,
         }
     }
 
@@ -92,94 +263,347 @@ impl LinuxController {
         &mut self,
         service_main_wrapper: LinuxServiceMainWrapperFn,
     ) -> Result<(), Error> {
-        service_main_wrapper(env::args().collect());
+        let args: Vec<String> = env::args().collect();
+        if self.instance.is_none() {
+            self.instance = instance_name(&args);
+        }
+
+        let exit_code = {
+            let _pid_file = match self.get_pid_file_path() {
+                Some(ref path) if self.daemonize => {
+                    Some(daemonize(path, Path::new("/"), None, None)?)
+                }
+                Some(ref path) => Some(PidFile::acquire(path)?),
+                None => None,
+            };
+            set_pause_signals(self.pause_signals);
+            set_reopen_logs(self.log_file.is_some());
+            #[cfg(feature = "metrics")]
+            set_unit_name(self.get_unit_base_name());
+            #[cfg(feature = "control")]
+            set_control_socket(
+                self.control_socket
+                    .then(|| control::default_path(&self.get_unit_base_name())),
+            );
+            service_main_wrapper(args)
+        };
+
+        // Scheduled services exit with the return code of the service main function, once the pid file
+        // is released.
+        if self.schedule.is_some() {
+            process::exit(exit_code as i32);
+        }
         Ok(())
     }
 
+    /// Renders the unit files installed by `create`, with their paths relative to the root directory.
+    pub fn render_systemd(&self) -> Result<Vec<ServiceFile>, Error> {
+        Ok(self.get_service_definition()?.render_systemd())
+    }
+
+    /// Sends `command` to the control socket of the running service, at its default path.
+    #[cfg(feature = "control")]
+    pub fn send_command<C: serde::Serialize + ?Sized>(&self, command: &C) -> Result<(), Error> {
+        control::send_command(&control::default_path(&self.get_unit_base_name()), command)
+    }
+
+    /// Sends `command` to the control socket of the running service and returns the data of its reply.
+    #[cfg(feature = "control")]
+    pub fn request<C: serde::Serialize + ?Sized, R: serde::de::DeserializeOwned>(
+        &self,
+        command: &C,
+    ) -> Result<R, Error> {
+        control::request(&control::default_path(&self.get_unit_base_name()), command)
+    }
+
+    fn send_pause_event(&self, pause: bool) -> Result<(), Error> {
+        match self.pause_signals {
+            Some((pause_signal, continue_signal)) => systemd_kill_daemon(
+                &self.get_service_file_name(),
+                if pause { pause_signal } else { continue_signal },
+            ),
+            #[cfg(feature = "control")]
+            None if self.control_socket => self.send_command(&match pause {
+                true => ControlEvent::Pause,
+                false => ControlEvent::Continue,
+            }),
+            None => Err(Error::new(
+                "Pause signals are disabled and the control socket is not enabled",
+            )),
+        }
+    }
+
+    fn get_pid_file_path(&self) -> Option<PathBuf> {
+        match self.pid_file {
+            Some(ref path) => Some(path.clone()),
+            None if self.daemonize => Some(PidFile::default_path(&self.get_unit_base_name())),
+            None => None,
+        }
+    }
+
+    /// Pid file path as written in the unit file. The template unit is shared by every instance, which
+    /// expands `%i` in the path.
+    fn get_unit_pid_file_path(&self) -> Option<PathBuf> {
+        match (&self.pid_file, &self.instance) {
+            (None, Some(_)) if self.daemonize => {
+                Some(PidFile::default_path(&self.get_unit_template_name()))
+            }
+            _ => self.get_pid_file_path(),
+        }
+    }
+
+    /// Name of the units of the service, `<service_name>@<instance>` for an instance of a template.
+    fn get_unit_base_name(&self) -> String {
+        match self.instance {
+            Some(ref instance) => format!("{}@{}", self.service_name, escape_unit_name(instance)),
+            None => self.service_name.clone(),
+        }
+    }
+
+    /// Name of the units of the service as written in the unit file, where `%i` expands to the instance.
+    fn get_unit_template_name(&self) -> String {
+        match self.instance {
+            Some(_) => format!("{}@%i", self.service_name),
+            None => self.service_name.clone(),
+        }
+    }
+
     fn get_service_file_name(&self) -> String {
-        format!("{}.service", &self.service_name)
+        format!("{}.service", self.get_unit_base_name())
+    }
+
+    /// Unit managed by the controller, the timer of a scheduled service.
+    fn get_unit_name(&self) -> String {
+        match self.schedule {
+            Some(_) => self.get_timer_file_name(),
+            None => self.get_service_file_name(),
+        }
+    }
+
+    fn get_timer_file_name(&self) -> String {
+        format!("{}.timer", self.get_unit_base_name())
+    }
+
+    /// Name of the installed unit file of type `unit_type`, the template unit for an instance.
+    fn get_unit_file_name(&self, unit_type: &str) -> String {
+        match self.instance {
+            Some(_) => format!("{}@.{}", self.service_name, unit_type),
+            None => format!("{}.{}", self.service_name, unit_type),
+        }
     }
 
     fn get_service_unit_path(&self) -> PathBuf {
-        Path::new("/lib/systemd/system/").join(self.get_service_file_name())
+        Path::new("/lib/systemd/system/").join(self.get_unit_file_name("service"))
+    }
+
+    fn get_timer_unit_path(&self) -> PathBuf {
+        Path::new("/lib/systemd/system/").join(self.get_unit_file_name("timer"))
     }
 
     fn get_service_dropin_dir(&self) -> PathBuf {
-        Path::new("/lib/systemd/system/").join(format!("{}.d", self.get_service_file_name()))
+        Path::new("/lib/systemd/system/").join(format!("{}.d", self.get_unit_file_name("service")))
     }
 
-    fn get_service_unit_content(&self) -> Result<String, Error> {
-        Ok(format!(
-            r#"
-[Unit]
-Description={}
+    fn get_sysusers_path(&self) -> PathBuf {
+        Path::new("/usr/lib/sysusers.d/").join(format!("{}.conf", self.service_name))
+    }
 
-[Service]
-ExecStart={}
+    fn get_logrotate_path(&self) -> PathBuf {
+        Path::new("/etc/logrotate.d/").join(self.get_unit_base_name())
+    }
 
-[Install]
-WantedBy=multi-user.target"#,
-            self.service_name,
-            fs::read_link("/proc/self/exe")
-                .map_err(|e| Error::new(&format!("Failed to read /proc/self/exe: {}", e)))?
-                .to_str()
-                .ok_or("Failed to parse /proc/self/exe")?
-        ))
+    fn get_service_definition(&self) -> Result<ServiceDefinition, Error> {
+        let mut definition = ServiceDefinition::new(
+            &self.service_name,
+            &self.display_name,
+            &self.description,
+            &current_exe()?,
+        );
+        definition.working_directory = self.working_directory.clone();
+        definition.environment = self.environment.clone();
+        definition.environment_files = self.environment_files.clone();
+        definition.user = self.user.clone();
+        definition.group = self.group.clone();
+        definition.supplementary_groups = self.supplementary_groups.clone();
+        definition.dynamic_user = self.dynamic_user;
+        definition.hardening = self.hardening.clone();
+        definition.restart = self.restart.clone();
+        definition.dependencies = self.dependencies.clone();
+        definition.schedule = self.schedule.clone();
+        definition.template = self.instance.is_some();
+        if self.daemonize {
+            definition.pid_file = self
+                .get_unit_pid_file_path()
+                .map(|path| path.to_string_lossy().into_owned());
+        }
+        // The pid file, the control socket and the last exit code live in the runtime directory, which stays writable with
+        // `ProtectSystem=strict` and is owned by the user of the service.
+        if let Some(path) = self.get_unit_pid_file_path() {
+            definition
+                .hardening
+                .allow_writes_to(&path.to_string_lossy());
+        }
+        #[cfg(feature = "control")]
+        if self.control_socket {
+            let path = control::default_path(&self.get_unit_template_name());
+            definition
+                .hardening
+                .allow_writes_to(&path.to_string_lossy());
+        }
+        #[cfg(feature = "metrics")]
+        {
+            let path = metrics::last_exit_code_path(&self.get_unit_template_name());
+            definition
+                .hardening
+                .allow_writes_to(&path.to_string_lossy());
+        }
+        definition.config = self.config.clone();
+
+        Ok(definition)
     }
 
-    fn write_service_config(&self) -> Result<(), Error> {
+    /// Whether an instance of the template is enabled, from the links in `/etc/systemd/system/*.wants`.
+    fn has_enabled_instances(&self) -> bool {
+        let prefix = format!("{}@", self.service_name);
+        let entries = match fs::read_dir("/etc/systemd/system/") {
+            Ok(entries) => entries,
+            Err(_) => return false,
+        };
+
+        entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().ends_with(".wants"))
+            .filter_map(|entry| fs::read_dir(entry.path()).ok())
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .any(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+    }
+
+    fn delete_unit_files(&self) {
         let path = self.get_service_unit_path();
-        let content = self.get_service_unit_content()?;
-        info!("Writing service file {}", path.display());
+        fs::remove_file(&path)
+            .map_err(|e| debug!("Failed to delete {}: {}", path.display(), e))
+            .ok();
+
+        if self.schedule.is_some() {
+            let path = self.get_timer_unit_path();
+            fs::remove_file(&path)
+                .map_err(|e| debug!("Failed to delete {}: {}", path.display(), e))
+                .ok();
+        }
+
+        let path = self.get_service_dropin_dir();
+        fs::remove_dir_all(self.get_service_dropin_dir())
+            .map_err(|e| debug!("Failed to delete {}: {}", path.display(), e))
+            .ok();
+
+        if self.sysusers {
+            let path = self.get_sysusers_path();
+            fs::remove_file(&path)
+                .map_err(|e| debug!("Failed to delete {}: {}", path.display(), e))
+                .ok();
+        }
+    }
+
+    fn write_service_config(&self) -> Result<(), Error> {
+        for file in self.render_systemd()? {
+            info!("Writing {}", Path::new("/").join(&file.path).display());
+            file.write(Path::new("/"))?;
+        }
+
+        Ok(())
+    }
+
+    fn write_sysusers_config(&self) -> Result<(), Error> {
+        let user = self
+            .user
+            .as_ref()
+            .ok_or("A user is required to create a sysusers.d snippet")?;
+
+        let path = self.get_sysusers_path();
+        let content = sysusers_snippet(
+            user,
+            self.group.as_deref(),
+            &self.supplementary_groups,
+            &self.display_name,
+        );
+        info!("Writing sysusers.d file {}", path.display());
         File::create(&path)
             .and_then(|mut file| file.write_all(content.as_bytes()))
             .map_err(|e| Error::new(&format!("Failed to write {}: {}", path.display(), e)))?;
 
-        if let Some(ref config) = self.config {
-            let path = self
-                .get_service_dropin_dir()
-                .join(format!("{}.conf", self.service_name));
-            fs::create_dir(path.parent().unwrap())
-                .map_err(|e| Error::new(&format!("Failed to create {}: {}", path.display(), e)))?;
-            info!("Writing config file {}", path.display());
-            File::create(&path)
-                .and_then(|mut file| file.write_all(config.as_bytes()))
-                .map_err(|e| Error::new(&format!("Failed to write {}: {}", path.display(), e)))?;
-        }
+        systemd_create_sysusers(&path)
+    }
 
-        Ok(())
+    fn write_logrotate_config(&self, log_file: &Path) -> Result<(), Error> {
+        let path = self.get_logrotate_path();
+        let postrotate = format!(
+            "systemctl kill --kill-whom=main --signal=HUP {} >/dev/null 2>&1 || true",
+            self.get_service_file_name()
+        );
+        let content = logging::logrotate_snippet(log_file, &self.log_rotation, Some(&postrotate));
+        info!("Writing logrotate file {}", path.display());
+        File::create(&path)
+            .and_then(|mut file| file.write_all(content.as_bytes()))
+            .map_err(|e| Error::new(&format!("Failed to write {}: {}", path.display(), e)))
     }
 }
 
 impl ControllerInterface for LinuxController {
     fn create(&mut self) -> Result<(), Error> {
+        if self.sysusers {
+            self.write_sysusers_config()?;
+        }
+
         self.write_service_config()?;
 
-        systemd_install_daemon(&self.service_name)
+        if let Some(ref log_file) = self.log_file {
+            self.write_logrotate_config(log_file)?;
+        }
+
+        systemd_install_daemon(&self.get_unit_name())
     }
 
     fn delete(&mut self) -> Result<(), Error> {
-        systemd_uninstall_daemon(&self.service_name)?;
-
-        let path = self.get_service_unit_path();
-        fs::remove_file(&path)
-            .map_err(|e| debug!("Failed to delete {}: {}", path.display(), e))
-            .ok();
+        systemd_uninstall_daemon(&self.get_unit_name())?;
+
+        if self.instance.is_some() && self.has_enabled_instances() {
+            info!(
+                "Keeping the template units of {}, other instances are enabled",
+                self.service_name
+            );
+        } else {
+            self.delete_unit_files();
+        }
 
-        let path = self.get_service_dropin_dir();
-        fs::remove_dir_all(self.get_service_dropin_dir())
-            .map_err(|e| debug!("Failed to delete {}: {}", path.display(), e))
-            .ok();
+        if self.log_file.is_some() {
+            let path = self.get_logrotate_path();
+            fs::remove_file(&path)
+                .map_err(|e| debug!("Failed to delete {}: {}", path.display(), e))
+                .ok();
+        }
 
         Ok(())
     }
 
     fn start(&mut self) -> Result<(), Error> {
-        systemd_start_daemon(&self.service_name)
+        systemd_start_daemon(&self.get_unit_name())
     }
 
     fn stop(&mut self) -> Result<(), Error> {
-        systemd_stop_daemon(&self.service_name)
+        systemd_stop_daemon(&self.get_unit_name())
+    }
+
+    fn status(&mut self) -> Result<ServiceStatus, Error> {
+        systemd_status_daemon(&self.get_unit_name())
+    }
+
+    fn pause(&mut self) -> Result<(), Error> {
+        self.send_pause_event(true)
+    }
+
+    fn resume(&mut self) -> Result<(), Error> {
+        self.send_pause_event(false)
     }
 }
 
@@ -235,15 +659,64 @@ fn run_monitor<T: Send + 'static>(
 #[macro_export]
 macro_rules! Service {
     ($name:expr, $function:ident) => {
-        extern "system" fn service_main_wrapper(args: Vec<String>) {
-            dispatch($function, args);
+        fn service_main_wrapper(args: Vec<String>) -> u32 {
+            dispatch($function, $name, args)
+        }
+    };
+    ($name:expr, $function:ident, control) => {
+        fn service_main_wrapper(args: Vec<String>) -> u32 {
+            dispatch_with_control($function, $name, args)
         }
     };
 }
 
 #[doc(hidden)]
-pub fn dispatch<T: Send + 'static>(service_main: ServiceMainFn<T>, args: Vec<String>) {
+pub fn dispatch<T: Send + 'static>(
+    service_main: ServiceMainFn<T>,
+    name: &str,
+    args: Vec<String>,
+) -> u32 {
+    run_service(service_main, name, args, |_tx| {
+        #[cfg(feature = "control")]
+        if control_socket().is_some() {
+            warn!(
+                "The control socket is only served by services declared with Service!(.., control)"
+            );
+        }
+    })
+}
+
+/// Same as `dispatch`, and delivers the commands received on the control socket of the controller to
+/// the service main function. `Service!(name, function, control)` dispatches with it.
+#[doc(hidden)]
+#[cfg(feature = "control")]
+pub fn dispatch_with_control<T: control::ControlCommand + Send + 'static>(
+    service_main: ServiceMainFn<T>,
+    name: &str,
+    args: Vec<String>,
+) -> u32 {
+    run_service(service_main, name, args, |tx| {
+        if let Some(path) = control_socket() {
+            match control::ControlSocket::bind(&path) {
+                Ok(socket) => socket.forward_events(tx.clone()),
+                Err(e) => warn!("{}", e),
+            }
+        }
+    })
+}
+
+/// Runs the service main function, `listen` setting up additional event sources once logging is set up.
+fn run_service<T: Send + 'static>(
+    service_main: ServiceMainFn<T>,
+    name: &str,
+    args: Vec<String>,
+    listen: impl FnOnce(&mpsc::Sender<ServiceEvent<T>>),
+) -> u32 {
     let (tx, rx) = mpsc::channel();
+    #[cfg(feature = "metrics")]
+    let unit_name = unit_name().unwrap_or_else(|| name.to_string());
+    #[cfg(feature = "metrics")]
+    let rx = metrics::observe(&unit_name, rx);
 
     #[cfg(feature = "systemd-rs")]
     {
@@ -256,5 +729,23 @@ pub fn dispatch<T: Send + 'static>(service_main: ServiceMainFn<T>, args: Vec<Str
         let _ = tx.send(ServiceEvent::Stop);
     })
     .expect("Failed to register Ctrl-C handler");
-    service_main(rx, _tx, args, false);
+
+    // The Ctrl-C handler stops the service on SIGHUP, replace it when logrotate signals the service.
+    if reopen_logs() {
+        logging::reopen_on_sighup()
+            .map_err(|e| eprintln!("{}", e))
+            .ok();
+    }
+    logging::setup(name, false)
+        .map_err(|e| eprintln!("{}", e))
+        .ok();
+    forward_pause_signals(_tx.clone())
+        .map_err(|e| warn!("{}", e))
+        .ok();
+    listen(&_tx);
+
+    let exit_code = service_main(rx, _tx, args, false);
+    #[cfg(feature = "metrics")]
+    metrics::record_exit_code(&unit_name, exit_code);
+    exit_code
 }
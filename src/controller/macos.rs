@@ -5,7 +5,7 @@ use std::{
     fs::{self, File},
     io::Write,
     path::{Path, PathBuf},
-    process::Command,
+    process::{self, Command},
     ptr,
     sync::{
         atomic::{AtomicBool, Ordering},
@@ -16,7 +16,7 @@ use std::{
 
 use chrono;
 use ctrlc;
-use log::info;
+use log::{info, warn};
 use timer;
 
 use core_foundation::{
@@ -37,12 +37,25 @@ use system_configuration_sys::{
     dynamic_store_copy_specific::{uid_t, SCDynamicStoreCopyConsoleUser},
 };
 
+use super::pause::{forward_pause_signals, set_pause_signals};
+#[cfg(feature = "control")]
+use crate::control::{self, ControlEvent};
+#[cfg(feature = "control")]
+use crate::controller::{control_socket, set_control_socket};
 use crate::controller::{ControllerInterface, ServiceMainFn};
+use crate::definition::ServiceDefinition;
+use crate::dependency::Dependency;
+use crate::launchd::LaunchdPlist;
+use crate::logging;
+#[cfg(feature = "metrics")]
+use crate::metrics;
+use crate::restart::RestartPolicy;
+use crate::schedule::Schedule;
 use crate::session;
 use crate::Error;
 use crate::ServiceEvent;
 
-type MacosServiceMainWrapperFn = extern "system" fn(args: Vec<String>);
+type MacosServiceMainWrapperFn = fn(args: Vec<String>) -> u32;
 pub type Session = session::Session_<u32>;
 
 pub enum LaunchAgentTargetSesssion {
@@ -111,6 +124,27 @@ fn launchctl_start_daemon(name: &str) -> Result<(), Error> {
     Ok(())
 }
 
+fn launchctl_kill_daemon(name: &str, signal: i32, is_agent: bool) -> Result<(), Error> {
+    let target = match is_agent {
+        true => format!("gui/{}/{}", unsafe { libc::getuid() }, name),
+        false => format!("system/{}", name),
+    };
+    let output = Command::new("launchctl")
+        .arg("kill")
+        .arg(signal.to_string())
+        .arg(&target)
+        .output()
+        .map_err(|e| Error::new(&format!("Failed to signal {}: {}", name, e)))?;
+    if !output.status.success() {
+        return Err(Error::new(&format!(
+            "Failed to signal {}: {}",
+            name,
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+    Ok(())
+}
+
 fn launchctl_stop_daemon(name: &str) -> Result<(), Error> {
     let output = Command::new("launchctl")
         .arg("stop")
@@ -130,7 +164,30 @@ pub struct MacosController {
     pub description: String,
     pub is_agent: bool,
     pub session_types: Option<Vec<LaunchAgentTargetSesssion>>,
-    pub keep_alive: bool,
+    /// Restart policy of the service (`KeepAlive` and `ThrottleInterval`), the service is always restarted by default.
+    pub restart: RestartPolicy,
+    /// Jobs the service requires, it is only kept alive while they are loaded (`KeepAlive` `OtherJobEnabled`).
+    pub dependencies: Vec<Dependency>,
+    /// Environment variables set for the service (`EnvironmentVariables`).
+    pub environment: Vec<(String, String)>,
+    /// User the daemon runs as (`UserName`).
+    pub user_name: Option<String>,
+    /// File the standard output of the service is written to (`StandardOutPath`).
+    pub standard_out_path: Option<String>,
+    /// File the standard error of the service is written to (`StandardErrorPath`).
+    pub standard_error_path: Option<String>,
+    /// Signals delivering `ServiceEvent::Pause` and `ServiceEvent::Continue`, `SIGUSR1` and `SIGUSR2` by
+    /// default, which leaves `SIGTSTP` (Ctrl-Z) to the terminal. When unset, `pause()` and `resume()` go
+    /// through the control socket if `control_socket` is set, and fail otherwise.
+    pub pause_signals: Option<(i32, i32)>,
+    /// Listens on the control socket at `control::default_path()` while the service runs, delivering the
+    /// commands sent with `send_command()` to the service. The service must be declared with
+    /// `Service!(name, function, control)`, whose custom events are deserialized from the commands.
+    #[cfg(feature = "control")]
+    pub control_socket: bool,
+    /// Runs the service periodically (`StartCalendarInterval` and `StartInterval`) instead of keeping it
+    /// alive, the service then runs once and exits with the return code of the service main function.
+    pub schedule: Option<Schedule>,
 }
 
 impl MacosController {
@@ -141,7 +198,16 @@ impl MacosController {
             description: description.to_string(),
             is_agent: false,
             session_types: None,
-            keep_alive: true,
+            restart: RestartPolicy::always(),
+            dependencies: Vec::new(),
+            environment: Vec::new(),
+            user_name: None,
+            standard_out_path: None,
+            standard_error_path: None,
+            pause_signals: Some((libc::SIGUSR1, libc::SIGUSR2)),
+            #[cfg(feature = "control")]
+            control_socket: false,
+            schedule: None,
         }
     }
 
@@ -150,10 +216,55 @@ impl MacosController {
         &mut self,
         service_main_wrapper: MacosServiceMainWrapperFn,
     ) -> Result<(), Error> {
-        service_main_wrapper(env::args().collect());
+        set_pause_signals(self.pause_signals);
+        #[cfg(feature = "control")]
+        set_control_socket(
+            self.control_socket
+                .then(|| control::default_path(&self.service_name)),
+        );
+        let exit_code = service_main_wrapper(env::args().collect());
+
+        // Scheduled services exit with the return code of the service main function, once dispatch
+        // stopped the session monitor.
+        if self.schedule.is_some() {
+            process::exit(exit_code as i32);
+        }
         Ok(())
     }
 
+    /// Sends `command` to the control socket of the running service, at its default path.
+    #[cfg(feature = "control")]
+    pub fn send_command<C: serde::Serialize + ?Sized>(&self, command: &C) -> Result<(), Error> {
+        control::send_command(&control::default_path(&self.service_name), command)
+    }
+
+    /// Sends `command` to the control socket of the running service and returns the data of its reply.
+    #[cfg(feature = "control")]
+    pub fn request<C: serde::Serialize + ?Sized, R: serde::de::DeserializeOwned>(
+        &self,
+        command: &C,
+    ) -> Result<R, Error> {
+        control::request(&control::default_path(&self.service_name), command)
+    }
+
+    fn send_pause_event(&self, pause: bool) -> Result<(), Error> {
+        match self.pause_signals {
+            Some((pause_signal, continue_signal)) => launchctl_kill_daemon(
+                &self.service_name,
+                if pause { pause_signal } else { continue_signal },
+                self.is_agent,
+            ),
+            #[cfg(feature = "control")]
+            None if self.control_socket => self.send_command(&match pause {
+                true => ControlEvent::Pause,
+                false => ControlEvent::Continue,
+            }),
+            None => Err(Error::new(
+                "Pause signals are disabled and the control socket is not enabled",
+            )),
+        }
+    }
+
     fn get_plist_content(&self) -> Result<String, Error> {
         let mut current_exe = env::current_exe()
             .map_err(|e| Error::new(&format!("env::current_exe() failed: {}", e)))?;
@@ -167,68 +278,30 @@ impl MacosController {
             .to_str()
             .expect("working_dir path to be unicode");
 
-        let mut plist = String::new();
-        plist.push_str(r#"
-<?xml version="1.0" encoding="UTF-8"?>
-<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
-<plist version="1.0">
-<dict>"#);
-
-        plist.push_str(&format!(
-            r#"
-<key>Disabled</key>
-<false/>
-<key>Label</key>
-<string>{}</string>
-<key>ProgramArguments</key>
-<array>
-<string>{}</string>
-</array>
-<key>WorkingDirectory</key>
-<string>{}</string>
-<key>RunAtLoad</key>
-<true/>"#,
-            self.service_name, current_exe_str, working_dir_str,
-        ));
-
+        let mut definition = ServiceDefinition::new(
+            &self.service_name,
+            &self.display_name,
+            &self.description,
+            &current_exe_str,
+        );
+        definition.working_directory = Some(working_dir_str.to_string());
+        definition.environment = self.environment.clone();
+        definition.user = self.user_name.clone();
+        definition.restart = self.restart.clone();
+        definition.dependencies = self.dependencies.clone();
+        definition.schedule = self.schedule.clone();
+
+        let mut plist = LaunchdPlist::from_definition(&definition);
         if self.is_agent {
             if let Some(session_types) = self.session_types.as_ref() {
-                plist.push_str(
-                    r#"
-<key>LimitLoadToSessionType</key>
-<array>"#,
-                );
-
-                for session_type in session_types {
-                    plist.push_str(&format!(
-                        r#"
-<string>{}</string>"#,
-                        session_type
-                    ));
-                }
-
-                plist.push_str(
-                    r#"
-</array>"#,
-                );
+                plist.limit_load_to_session_type =
+                    session_types.iter().map(|s| s.to_string()).collect();
             }
         }
+        plist.standard_out_path = self.standard_out_path.clone();
+        plist.standard_error_path = self.standard_error_path.clone();
 
-        if self.keep_alive {
-            plist.push_str(
-                r#"
-<key>KeepAlive</key>
-<true/>"#,
-            );
-        }
-
-        plist.push_str(
-            r#"
-</dict>
-</plist>"#,
-        );
-
-        Ok(plist)
+        Ok(plist.render())
     }
 
     fn write_plist(&self, path: &Path) -> Result<(), Error> {
@@ -278,6 +351,14 @@ impl ControllerInterface for MacosController {
     fn stop(&mut self) -> Result<(), Error> {
         launchctl_stop_daemon(&self.service_name)
     }
+    /// Pauses the service.
+    fn pause(&mut self) -> Result<(), Error> {
+        self.send_pause_event(true)
+    }
+    /// Resumes the service.
+    fn resume(&mut self) -> Result<(), Error> {
+        self.send_pause_event(false)
+    }
     // Loads the agent service.
     fn load(&mut self) -> Result<(), Error> {
         launchctl_load_daemon(&self.plist_path())
@@ -292,8 +373,13 @@ impl ControllerInterface for MacosController {
 #[macro_export]
 macro_rules! Service {
     ($name:expr, $function:ident) => {
-        extern "system" fn service_main_wrapper(args: Vec<String>) {
-            dispatch($function, args);
+        fn service_main_wrapper(args: Vec<String>) -> u32 {
+            dispatch($function, $name, args)
+        }
+    };
+    ($name:expr, $function:ident, control) => {
+        fn service_main_wrapper(args: Vec<String>) -> u32 {
+            dispatch_with_control($function, $name, args)
         }
     };
 }
@@ -485,8 +571,50 @@ pub fn run_monitor<T: Send + 'static>(
 }
 
 #[doc(hidden)]
-pub fn dispatch<T: Send + 'static>(service_main: ServiceMainFn<T>, args: Vec<String>) {
+pub fn dispatch<T: Send + 'static>(
+    service_main: ServiceMainFn<T>,
+    name: &str,
+    args: Vec<String>,
+) -> u32 {
+    run_service(service_main, name, args, |_tx| {
+        #[cfg(feature = "control")]
+        if control_socket().is_some() {
+            warn!(
+                "The control socket is only served by services declared with Service!(.., control)"
+            );
+        }
+    })
+}
+
+/// Same as `dispatch`, and delivers the commands received on the control socket of the controller to
+/// the service main function. `Service!(name, function, control)` dispatches with it.
+#[doc(hidden)]
+#[cfg(feature = "control")]
+pub fn dispatch_with_control<T: control::ControlCommand + Send + 'static>(
+    service_main: ServiceMainFn<T>,
+    name: &str,
+    args: Vec<String>,
+) -> u32 {
+    run_service(service_main, name, args, |tx| {
+        if let Some(path) = control_socket() {
+            match control::ControlSocket::bind(&path) {
+                Ok(socket) => socket.forward_events(tx.clone()),
+                Err(e) => warn!("{}", e),
+            }
+        }
+    })
+}
+
+/// Runs the service main function, `listen` setting up additional event sources once logging is set up.
+fn run_service<T: Send + 'static>(
+    service_main: ServiceMainFn<T>,
+    name: &str,
+    args: Vec<String>,
+    listen: impl FnOnce(&mpsc::Sender<ServiceEvent<T>>),
+) -> u32 {
     let (tx, rx) = mpsc::channel();
+    #[cfg(feature = "metrics")]
+    let rx = metrics::observe(name, rx);
 
     let mut session_monitor = run_monitor(tx.clone()).expect("Failed to run session monitor");
     let _tx = tx.clone();
@@ -495,7 +623,19 @@ pub fn dispatch<T: Send + 'static>(service_main: ServiceMainFn<T>, args: Vec<Str
         let _ = tx.send(ServiceEvent::Stop);
     })
     .expect("Failed to register Ctrl-C handler");
-    service_main(rx, _tx, args, false);
+
+    logging::setup(name, false)
+        .map_err(|e| eprintln!("{}", e))
+        .ok();
+    forward_pause_signals(_tx.clone())
+        .map_err(|e| warn!("{}", e))
+        .ok();
+    listen(&_tx);
+
+    let exit_code = service_main(rx, _tx, args, false);
+    #[cfg(feature = "metrics")]
+    metrics::record_exit_code(name, exit_code);
 
     session_monitor.stop();
+    exit_code
 }
@@ -0,0 +1,247 @@
+use std::env;
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use log::{debug, info};
+
+use super::linux::{
+    check_environment_names, command_execute, command_exit_code, current_exe, shell_quote,
+    LinuxServiceMainWrapperFn,
+};
+use crate::controller::{ControllerInterface, ServiceStatus};
+use crate::definition::ServiceDefinition;
+use crate::Error;
+
+fn openrc_install_daemon(name: &str) -> Result<(), Error> {
+    command_execute("rc-update", &["add", name, "default"])
+}
+
+fn openrc_uninstall_daemon(name: &str) -> Result<(), Error> {
+    command_execute("rc-update", &["del", name, "default"])
+}
+
+fn openrc_start_daemon(name: &str) -> Result<(), Error> {
+    command_execute("rc-service", &[name, "start"])
+}
+
+fn openrc_stop_daemon(name: &str) -> Result<(), Error> {
+    command_execute("rc-service", &[name, "stop"])
+}
+
+fn openrc_status_daemon(name: &str) -> Result<ServiceStatus, Error> {
+    match command_exit_code("rc-service", &[name, "status"])? {
+        0 => Ok(ServiceStatus::Running),
+        _ => Ok(ServiceStatus::Stopped),
+    }
+}
+
+/// Generates an openrc-run script supervising `definition` with supervise-daemon.
+fn openrc_run_script(definition: &ServiceDefinition, depend: &[String]) -> String {
+    let mut script = String::from("#!/sbin/openrc-run\n\n");
+
+    let arguments: Vec<String> = definition
+        .arguments
+        .iter()
+        .map(|arg| shell_quote(arg))
+        .collect();
+
+    let _ = writeln!(script, "name={}", shell_quote(&definition.display_name));
+    let _ = writeln!(
+        script,
+        "description={}",
+        shell_quote(&definition.description)
+    );
+    let _ = writeln!(script, "supervisor=supervise-daemon");
+    let _ = writeln!(script, "command={}", shell_quote(&definition.executable));
+    if !arguments.is_empty() {
+        let _ = writeln!(script, "command_args={}", shell_quote(&arguments.join(" ")));
+    }
+    if let Some(ref user) = definition.user {
+        let command_user = match definition.group {
+            Some(ref group) => format!("{}:{}", user, group),
+            None => user.to_string(),
+        };
+        let _ = writeln!(script, "command_user={}", shell_quote(&command_user));
+    }
+    if let Some(ref path) = definition.working_directory {
+        let _ = writeln!(script, "directory={}", shell_quote(path));
+    }
+    for (name, value) in &definition.environment {
+        let _ = writeln!(script, "export {}={}", name, shell_quote(value));
+    }
+
+    if !depend.is_empty() {
+        script.push_str("\ndepend() {\n");
+        for line in depend {
+            let _ = writeln!(script, "\t{}", line);
+        }
+        script.push_str("}\n");
+    }
+
+    script
+}
+
+/// Manages the service through an OpenRC init script, as found on Alpine Linux.
+pub struct OpenRcController {
+    pub service_name: String,
+    pub display_name: String,
+    pub description: String,
+    /// Environment variables exported by the init script.
+    pub environment: Vec<(String, String)>,
+    /// User the service runs as (`command_user`), the service runs as root when unset.
+    pub user: Option<String>,
+    /// Group the service runs as, used with `user`.
+    pub group: Option<String>,
+    /// Lines of the `depend()` function, such as `need net` or `after firewall`.
+    pub depend: Vec<String>,
+}
+
+impl OpenRcController {
+    pub fn new(service_name: &str, display_name: &str, description: &str) -> OpenRcController {
+        OpenRcController {
+            service_name: service_name.to_string(),
+            display_name: display_name.to_string(),
+            description: description.to_string(),
+            environment: Vec::new(),
+            user: None,
+            group: None,
+            depend: vec!["need net".to_string()],
+        }
+    }
+
+    pub fn register(
+        &mut self,
+        service_main_wrapper: LinuxServiceMainWrapperFn,
+    ) -> Result<(), Error> {
+        service_main_wrapper(env::args().collect());
+        Ok(())
+    }
+
+    fn get_init_script_path(&self) -> PathBuf {
+        Path::new("/etc/init.d/").join(&self.service_name)
+    }
+
+    fn write_init_script(&self) -> Result<(), Error> {
+        let mut definition = ServiceDefinition::new(
+            &self.service_name,
+            &self.display_name,
+            &self.description,
+            &current_exe()?,
+        );
+        definition.environment = self.environment.clone();
+        definition.user = self.user.clone();
+        definition.group = self.group.clone();
+
+        let path = self.get_init_script_path();
+        let content = openrc_run_script(&definition, &self.depend);
+        info!("Writing init script {}", path.display());
+        File::create(&path)
+            .and_then(|mut file| file.write_all(content.as_bytes()))
+            .and_then(|_| fs::set_permissions(&path, fs::Permissions::from_mode(0o755)))
+            .map_err(|e| Error::new(&format!("Failed to write {}: {}", path.display(), e)))
+    }
+}
+
+impl ControllerInterface for OpenRcController {
+    fn create(&mut self) -> Result<(), Error> {
+        check_environment_names(&self.environment)?;
+        self.write_init_script()?;
+
+        openrc_install_daemon(&self.service_name)
+    }
+
+    fn delete(&mut self) -> Result<(), Error> {
+        openrc_stop_daemon(&self.service_name)
+            .map_err(|e| debug!("{}", e))
+            .ok();
+        openrc_uninstall_daemon(&self.service_name)?;
+
+        let path = self.get_init_script_path();
+        fs::remove_file(&path)
+            .map_err(|e| debug!("Failed to delete {}: {}", path.display(), e))
+            .ok();
+
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<(), Error> {
+        openrc_start_daemon(&self.service_name)
+    }
+
+    fn stop(&mut self) -> Result<(), Error> {
+        openrc_stop_daemon(&self.service_name)
+    }
+
+    fn status(&mut self) -> Result<ServiceStatus, Error> {
+        openrc_status_daemon(&self.service_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn supervise_daemon_script() {
+        let mut definition =
+            ServiceDefinition::new("foobar", "FooBar", "FooBar service", "/opt/foo bar/foobar");
+        definition.user = Some("foobar".to_string());
+        definition.group = Some("daemon".to_string());
+        definition.working_directory = Some("/var/lib/foobar".to_string());
+        definition.environment = vec![("GREETING".to_string(), "it's me".to_string())];
+
+        let depend = vec!["need net".to_string(), "after firewall".to_string()];
+        assert_eq!(
+            openrc_run_script(&definition, &depend),
+            "#!/sbin/openrc-run\n\
+             \n\
+             name='FooBar'\n\
+             description='FooBar service'\n\
+             supervisor=supervise-daemon\n\
+             command='/opt/foo bar/foobar'\n\
+             command_user='foobar:daemon'\n\
+             directory='/var/lib/foobar'\n\
+             export GREETING='it'\\''s me'\n\
+             \n\
+             depend() {\n\
+             \tneed net\n\
+             \tafter firewall\n\
+             }\n"
+        );
+
+        definition.group = None;
+        assert!(openrc_run_script(&definition, &[]).contains("command_user='foobar'\n"));
+    }
+
+    #[test]
+    fn command_args_round_trip() {
+        let arguments = ["--name", "it's $HOME", "two words", "`id`"];
+        let mut definition =
+            ServiceDefinition::new("foobar", "FooBar", "FooBar", "/usr/bin/foobar");
+        definition.arguments = arguments.iter().map(|arg| arg.to_string()).collect();
+
+        let script = openrc_run_script(&definition, &[]);
+        let command_args = script
+            .lines()
+            .find(|line| line.starts_with("command_args="))
+            .unwrap();
+
+        // openrc-run sources the script, then evaluates command_args when starting the daemon.
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(format!(
+                "{}\neval set -- $command_args\nprintf '%s\\n' \"$@\"",
+                command_args
+            ))
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            format!("{}\n", arguments.join("\n"))
+        );
+    }
+}
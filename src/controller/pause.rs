@@ -0,0 +1,87 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::unix::io::FromRawFd;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+use crate::controller::set_paused;
+use crate::Error;
+use crate::ServiceEvent;
+
+/// Signals delivering `ServiceEvent::Pause` and `ServiceEvent::Continue`.
+static PAUSE_SIGNALS: Mutex<Option<(i32, i32)>> = Mutex::new(Some((libc::SIGUSR1, libc::SIGUSR2)));
+
+/// Write end of the pipe the signal handler reports signals to.
+static SIGNAL_PIPE: AtomicI32 = AtomicI32::new(-1);
+
+pub(super) fn set_pause_signals(signals: Option<(i32, i32)>) {
+    if let Ok(mut current) = PAUSE_SIGNALS.lock() {
+        *current = signals;
+    }
+}
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    let fd = SIGNAL_PIPE.load(Ordering::SeqCst);
+    if fd >= 0 {
+        let byte = signal as u8;
+        unsafe { libc::write(fd, &byte as *const u8 as *const libc::c_void, 1) };
+    }
+}
+
+fn set_handler(signal: i32) -> Result<(), Error> {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+            return Err(Error::new(&format!(
+                "Failed to set handler of signal {}: {}",
+                signal,
+                io::Error::last_os_error()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Sends `Pause` and `Continue` events to `tx` when the pause signals are received, from a background
+/// thread. Signals that do not change the paused state are ignored.
+pub(super) fn forward_pause_signals<T: Send + 'static>(
+    tx: mpsc::Sender<ServiceEvent<T>>,
+) -> Result<(), Error> {
+    let (pause_signal, continue_signal) = match PAUSE_SIGNALS.lock().ok().and_then(|s| *s) {
+        Some(signals) => signals,
+        None => return Ok(()),
+    };
+
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err(Error::new(&format!(
+            "Failed to create signal pipe: {}",
+            io::Error::last_os_error()
+        )));
+    }
+    let mut reader = unsafe { File::from_raw_fd(fds[0]) };
+    SIGNAL_PIPE.store(fds[1], Ordering::SeqCst);
+
+    set_handler(pause_signal)?;
+    set_handler(continue_signal)?;
+
+    thread::spawn(move || {
+        let mut signal = [0u8; 1];
+        while reader.read_exact(&mut signal).is_ok() {
+            let event = match signal[0] as i32 {
+                signal if signal == pause_signal && set_paused(true) => ServiceEvent::Pause,
+                signal if signal == continue_signal && set_paused(false) => ServiceEvent::Continue,
+                _ => continue,
+            };
+            if tx.send(event).is_err() {
+                break;
+            }
+        }
+    });
+
+    Ok(())
+}
@@ -0,0 +1,425 @@
+//! runit and s6 controllers. Both supervisors run services from a directory holding an executable
+//! `run` script, and an optional `log/run` script for the logger, which is enabled by linking it into
+//! the directory scanned by the supervisor.
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::Write;
+use std::os::unix::fs::{symlink, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use log::{debug, info};
+
+use super::linux::{
+    check_environment_names, command_execute, current_exe, shell_quote, LinuxServiceMainWrapperFn,
+};
+use crate::controller::{ControllerInterface, ServiceStatus};
+use crate::definition::ServiceDefinition;
+use crate::Error;
+
+/// Generates the `run` script of `definition`, `setuid` being the program used to drop privileges.
+fn run_script(definition: &ServiceDefinition, setuid: &str) -> String {
+    let mut script = String::from("#!/bin/sh\nexec 2>&1\n");
+
+    for (name, value) in &definition.environment {
+        let _ = writeln!(script, "export {}={}", name, shell_quote(value));
+    }
+    if let Some(ref path) = definition.working_directory {
+        let _ = writeln!(script, "cd {} || exit 1", shell_quote(path));
+    }
+
+    script.push_str("exec ");
+    if let Some(ref user) = definition.user {
+        let _ = write!(script, "{} {} ", setuid, shell_quote(user));
+    }
+    let command_line: Vec<String> = std::iter::once(&definition.executable)
+        .chain(definition.arguments.iter())
+        .map(|arg| shell_quote(arg))
+        .collect();
+    script.push_str(&command_line.join(" "));
+    script.push('\n');
+
+    script
+}
+
+/// Generates the `log/run` script, `logger` being the logger command line without the log directory.
+fn log_run_script(log_dir: &Path, logger: &str) -> String {
+    let log_dir = shell_quote(&log_dir.to_string_lossy());
+    format!(
+        "#!/bin/sh\nmkdir -p {}\nexec {} {}\n",
+        log_dir, logger, log_dir
+    )
+}
+
+fn write_script(path: &Path, content: &str) -> Result<(), Error> {
+    info!("Writing {}", path.display());
+    File::create(path)
+        .and_then(|mut file| file.write_all(content.as_bytes()))
+        .and_then(|_| fs::set_permissions(path, fs::Permissions::from_mode(0o755)))
+        .map_err(|e| Error::new(&format!("Failed to write {}: {}", path.display(), e)))
+}
+
+fn write_service_dir(service_dir: &Path, run: &str, log_run: Option<String>) -> Result<(), Error> {
+    fs::create_dir_all(service_dir).map_err(|e| {
+        Error::new(&format!(
+            "Failed to create {}: {}",
+            service_dir.display(),
+            e
+        ))
+    })?;
+    write_script(&service_dir.join("run"), run)?;
+
+    if let Some(log_run) = log_run {
+        let log_service_dir = service_dir.join("log");
+        fs::create_dir_all(&log_service_dir).map_err(|e| {
+            Error::new(&format!(
+                "Failed to create {}: {}",
+                log_service_dir.display(),
+                e
+            ))
+        })?;
+        write_script(&log_service_dir.join("run"), &log_run)?;
+    }
+
+    Ok(())
+}
+
+fn enable_service_dir(service_dir: &Path, link: &Path) -> Result<(), Error> {
+    info!("Linking {} to {}", link.display(), service_dir.display());
+    symlink(service_dir, link)
+        .map_err(|e| Error::new(&format!("Failed to link {}: {}", link.display(), e)))
+}
+
+fn remove_service_dir(service_dir: &Path, link: &Path) {
+    fs::remove_file(link)
+        .map_err(|e| debug!("Failed to delete {}: {}", link.display(), e))
+        .ok();
+    fs::remove_dir_all(service_dir)
+        .map_err(|e| debug!("Failed to delete {}: {}", service_dir.display(), e))
+        .ok();
+}
+
+/// Pid of the service in the `supervise/status` file written by `runsv`, stored as a little-endian 32-bit
+/// integer after the 12-byte timestamp, and 0 when the service is down.
+fn runit_status_pid(status: &[u8]) -> Option<u64> {
+    let pid = status.get(12..16)?.try_into().ok()?;
+    Some(u32::from_le_bytes(pid).into())
+}
+
+/// Pid of the service in the `supervise/status` file written by `s6-supervise`, stored as a big-endian
+/// 64-bit integer after the two 12-byte timestamps, and 0 when the service is down.
+fn s6_status_pid(status: &[u8]) -> Option<u64> {
+    let pid = status.get(24..32)?.try_into().ok()?;
+    Some(u64::from_be_bytes(pid))
+}
+
+/// Reads the state of the service from the `supervise/status` file of `service_dir`, `status_pid`
+/// extracting the pid of the service from its content.
+fn read_status(
+    service_dir: &Path,
+    status_pid: fn(&[u8]) -> Option<u64>,
+) -> Result<ServiceStatus, Error> {
+    let path = service_dir.join("supervise").join("status");
+    let status = fs::read(&path)
+        .map_err(|e| Error::new(&format!("Failed to read {}: {}", path.display(), e)))?;
+
+    match status_pid(&status) {
+        Some(0) => Ok(ServiceStatus::Stopped),
+        Some(_) => Ok(ServiceStatus::Running),
+        None => Err(Error::new(&format!(
+            "Invalid status file {}",
+            path.display()
+        ))),
+    }
+}
+
+fn path_str(path: &Path) -> Result<&str, Error> {
+    path.to_str()
+        .ok_or_else(|| Error::new(&format!("Failed to parse {}", path.display())))
+}
+
+fn first_existing_dir(candidates: &[&str]) -> PathBuf {
+    let dir = candidates
+        .iter()
+        .find(|dir| Path::new(dir).is_dir())
+        .unwrap_or(&candidates[0]);
+    PathBuf::from(dir)
+}
+
+/// Manages the service through a runit service directory.
+pub struct RunitController {
+    pub service_name: String,
+    pub display_name: String,
+    pub description: String,
+    /// Environment variables exported by the `run` script.
+    pub environment: Vec<(String, String)>,
+    /// User the service runs as (through `chpst -u`), the service runs as root when unset.
+    pub user: Option<String>,
+    /// Service directory, defaults to `/etc/sv/<service_name>`.
+    pub service_dir: PathBuf,
+    /// Directory scanned by `runsvdir`, defaults to `/var/service` or `/etc/service`.
+    pub scan_dir: PathBuf,
+    /// Directory `svlogd` writes the service output to, no logger is set up when unset.
+    pub log_dir: Option<PathBuf>,
+}
+
+impl RunitController {
+    pub fn new(service_name: &str, display_name: &str, description: &str) -> RunitController {
+        RunitController {
+            service_name: service_name.to_string(),
+            display_name: display_name.to_string(),
+            description: description.to_string(),
+            environment: Vec::new(),
+            user: None,
+            service_dir: Path::new("/etc/sv/").join(service_name),
+            scan_dir: first_existing_dir(&["/var/service", "/etc/service"]),
+            log_dir: None,
+        }
+    }
+
+    pub fn register(
+        &mut self,
+        service_main_wrapper: LinuxServiceMainWrapperFn,
+    ) -> Result<(), Error> {
+        service_main_wrapper(env::args().collect());
+        Ok(())
+    }
+
+    fn get_link_path(&self) -> PathBuf {
+        self.scan_dir.join(&self.service_name)
+    }
+}
+
+impl ControllerInterface for RunitController {
+    fn create(&mut self) -> Result<(), Error> {
+        check_environment_names(&self.environment)?;
+        let mut definition = ServiceDefinition::new(
+            &self.service_name,
+            &self.display_name,
+            &self.description,
+            &current_exe()?,
+        );
+        definition.environment = self.environment.clone();
+        definition.user = self.user.clone();
+
+        let log_run = self
+            .log_dir
+            .as_ref()
+            .map(|log_dir| log_run_script(log_dir, "svlogd -tt"));
+        write_service_dir(
+            &self.service_dir,
+            &run_script(&definition, "chpst -u"),
+            log_run,
+        )?;
+
+        enable_service_dir(&self.service_dir, &self.get_link_path())
+    }
+
+    fn delete(&mut self) -> Result<(), Error> {
+        let link = self.get_link_path();
+        command_execute("sv", &["down", path_str(&link)?])
+            .map_err(|e| debug!("{}", e))
+            .ok();
+        remove_service_dir(&self.service_dir, &link);
+
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<(), Error> {
+        command_execute("sv", &["up", path_str(&self.get_link_path())?])
+    }
+
+    fn stop(&mut self) -> Result<(), Error> {
+        command_execute("sv", &["down", path_str(&self.get_link_path())?])
+    }
+
+    fn status(&mut self) -> Result<ServiceStatus, Error> {
+        read_status(&self.get_link_path(), runit_status_pid)
+    }
+}
+
+/// Manages the service through an s6 service directory.
+pub struct S6Controller {
+    pub service_name: String,
+    pub display_name: String,
+    pub description: String,
+    /// Environment variables exported by the `run` script.
+    pub environment: Vec<(String, String)>,
+    /// User the service runs as (through `s6-setuidgid`), the service runs as root when unset.
+    pub user: Option<String>,
+    /// Service directory, defaults to `/etc/s6/sv/<service_name>`.
+    pub service_dir: PathBuf,
+    /// Directory scanned by `s6-svscan`, defaults to `/run/service` or `/service`.
+    pub scan_dir: PathBuf,
+    /// Directory `s6-log` writes the service output to, no logger is set up when unset.
+    pub log_dir: Option<PathBuf>,
+}
+
+impl S6Controller {
+    pub fn new(service_name: &str, display_name: &str, description: &str) -> S6Controller {
+        S6Controller {
+            service_name: service_name.to_string(),
+            display_name: display_name.to_string(),
+            description: description.to_string(),
+            environment: Vec::new(),
+            user: None,
+            service_dir: Path::new("/etc/s6/sv/").join(service_name),
+            scan_dir: first_existing_dir(&["/run/service", "/service"]),
+            log_dir: None,
+        }
+    }
+
+    pub fn register(
+        &mut self,
+        service_main_wrapper: LinuxServiceMainWrapperFn,
+    ) -> Result<(), Error> {
+        service_main_wrapper(env::args().collect());
+        Ok(())
+    }
+
+    fn get_link_path(&self) -> PathBuf {
+        self.scan_dir.join(&self.service_name)
+    }
+
+    fn rescan(&self) -> Result<(), Error> {
+        command_execute("s6-svscanctl", &["-a", path_str(&self.scan_dir)?])
+    }
+}
+
+impl ControllerInterface for S6Controller {
+    fn create(&mut self) -> Result<(), Error> {
+        check_environment_names(&self.environment)?;
+        let mut definition = ServiceDefinition::new(
+            &self.service_name,
+            &self.display_name,
+            &self.description,
+            &current_exe()?,
+        );
+        definition.environment = self.environment.clone();
+        definition.user = self.user.clone();
+
+        let log_run = self
+            .log_dir
+            .as_ref()
+            .map(|log_dir| log_run_script(log_dir, "s6-log T"));
+        write_service_dir(
+            &self.service_dir,
+            &run_script(&definition, "s6-setuidgid"),
+            log_run,
+        )?;
+
+        enable_service_dir(&self.service_dir, &self.get_link_path())?;
+        self.rescan()
+    }
+
+    fn delete(&mut self) -> Result<(), Error> {
+        let link = self.get_link_path();
+        command_execute("s6-svc", &["-d", path_str(&link)?])
+            .map_err(|e| debug!("{}", e))
+            .ok();
+        remove_service_dir(&self.service_dir, &link);
+        self.rescan().map_err(|e| debug!("{}", e)).ok();
+
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<(), Error> {
+        command_execute("s6-svc", &["-u", path_str(&self.get_link_path())?])
+    }
+
+    fn stop(&mut self) -> Result<(), Error> {
+        command_execute("s6-svc", &["-d", path_str(&self.get_link_path())?])
+    }
+
+    fn status(&mut self) -> Result<ServiceStatus, Error> {
+        read_status(&self.get_link_path(), s6_status_pid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_script_quotes_arguments() {
+        let mut definition =
+            ServiceDefinition::new("foobar", "FooBar", "FooBar", "/opt/foo bar/foobar");
+        definition.arguments = vec!["--name".to_string(), "it's $HOME".to_string()];
+        definition.environment = vec![
+            ("GREETING".to_string(), "hello world".to_string()),
+            ("QUOTE".to_string(), "it's".to_string()),
+        ];
+        definition.working_directory = Some("/var/lib/foo bar".to_string());
+        definition.user = Some("foobar".to_string());
+
+        assert_eq!(
+            run_script(&definition, "chpst -u"),
+            "#!/bin/sh\n\
+             exec 2>&1\n\
+             export GREETING='hello world'\n\
+             export QUOTE='it'\\''s'\n\
+             cd '/var/lib/foo bar' || exit 1\n\
+             exec chpst -u 'foobar' '/opt/foo bar/foobar' '--name' 'it'\\''s $HOME'\n"
+        );
+    }
+
+    #[test]
+    fn run_script_without_user() {
+        let definition = ServiceDefinition::new("foobar", "FooBar", "FooBar", "/usr/bin/foobar");
+        assert_eq!(
+            run_script(&definition, "s6-setuidgid"),
+            "#!/bin/sh\nexec 2>&1\nexec '/usr/bin/foobar'\n"
+        );
+    }
+
+    #[test]
+    fn runit_status() {
+        let mut status = [0u8; 20];
+        assert_eq!(runit_status_pid(&status), Some(0));
+
+        status[12..16].copy_from_slice(&4321u32.to_le_bytes());
+        assert_eq!(runit_status_pid(&status), Some(4321));
+
+        assert_eq!(runit_status_pid(&status[..15]), None);
+        assert_eq!(runit_status_pid(&[]), None);
+    }
+
+    #[test]
+    fn s6_status() {
+        let mut status = [0u8; 35];
+        assert_eq!(s6_status_pid(&status), Some(0));
+
+        status[24..32].copy_from_slice(&4321u64.to_be_bytes());
+        assert_eq!(s6_status_pid(&status), Some(4321));
+
+        assert_eq!(s6_status_pid(&status[..31]), None);
+        assert_eq!(s6_status_pid(&[]), None);
+    }
+
+    #[test]
+    fn read_status_file() {
+        let service_dir = env::temp_dir().join(format!("ceviche-supervise-{}", std::process::id()));
+        fs::create_dir_all(service_dir.join("supervise")).unwrap();
+        let path = service_dir.join("supervise").join("status");
+
+        let mut status = [0u8; 20];
+        fs::write(&path, status).unwrap();
+        assert!(matches!(
+            read_status(&service_dir, runit_status_pid),
+            Ok(ServiceStatus::Stopped)
+        ));
+
+        status[12..16].copy_from_slice(&4321u32.to_le_bytes());
+        fs::write(&path, status).unwrap();
+        assert!(matches!(
+            read_status(&service_dir, runit_status_pid),
+            Ok(ServiceStatus::Running)
+        ));
+
+        fs::write(&path, &status[..8]).unwrap();
+        assert!(read_status(&service_dir, runit_status_pid).is_err());
+
+        fs::remove_dir_all(&service_dir).unwrap();
+    }
+}
@@ -0,0 +1,374 @@
+use std::env;
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::Write;
+use std::iter::once;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use log::{debug, info};
+
+use super::linux::{
+    check_environment_names, command_execute, command_exit_code, current_exe, program_exists,
+    shell_quote, LinuxServiceMainWrapperFn,
+};
+use crate::controller::{ControllerInterface, ServiceStatus};
+use crate::definition::ServiceDefinition;
+use crate::Error;
+
+fn sysv_register_script(name: &str) -> Result<(), Error> {
+    if program_exists("update-rc.d") {
+        command_execute("update-rc.d", &[name, "defaults"])
+    } else if program_exists("chkconfig") {
+        command_execute("chkconfig", &["--add", name])
+    } else {
+        Err(Error::new(
+            "Neither update-rc.d nor chkconfig is available to register the init script",
+        ))
+    }
+}
+
+fn sysv_unregister_script(name: &str) -> Result<(), Error> {
+    if program_exists("update-rc.d") {
+        command_execute("update-rc.d", &["-f", name, "remove"])
+    } else if program_exists("chkconfig") {
+        command_execute("chkconfig", &["--del", name])
+    } else {
+        Ok(())
+    }
+}
+
+/// Generates an LSB init script that runs `definition` in the background and tracks it through `pid_file`.
+fn sysv_init_script(definition: &ServiceDefinition, pid_file: &Path) -> String {
+    let mut script = String::new();
+    let _ = write!(
+        script,
+        r#"#!/bin/sh
+### BEGIN INIT INFO
+# Provides:          {name}
+# Required-Start:    $remote_fs $syslog $network
+# Required-Stop:     $remote_fs $syslog $network
+# Default-Start:     2 3 4 5
+# Default-Stop:      0 1 6
+# Short-Description: {display_name}
+# Description:       {description}
+### END INIT INFO
+# chkconfig: 2345 90 10
+# description: {description}
+
+NAME={quoted_name}
+PIDFILE={pid_file}
+"#,
+        name = definition.service_name,
+        display_name = definition.display_name.replace('\n', " "),
+        description = definition.description.replace('\n', " "),
+        quoted_name = shell_quote(&definition.service_name),
+        pid_file = shell_quote(&pid_file.to_string_lossy()),
+    );
+
+    for (name, value) in &definition.environment {
+        let _ = writeln!(script, "export {}={}", name, shell_quote(value));
+    }
+
+    // The pid file must hold the pid of the service itself: start-stop-daemon writes it, and setpriv
+    // executes the service in place of the background shell whose pid `$!` holds.
+    let arguments: Vec<String> = definition
+        .arguments
+        .iter()
+        .map(|arg| shell_quote(arg))
+        .collect();
+    let executable = shell_quote(&definition.executable);
+    let command_line = once(executable.clone())
+        .chain(arguments.iter().cloned())
+        .collect::<Vec<String>>()
+        .join(" ");
+    let start_stop_daemon = format!(
+        "start-stop-daemon --start --quiet --background --make-pidfile --pidfile \"$PIDFILE\"{} --startas {} -- {}",
+        match (&definition.user, &definition.group) {
+            (Some(user), Some(group)) => format!(" --chuid {}", shell_quote(&format!("{}:{}", user, group))),
+            (Some(user), None) => format!(" --chuid {}", shell_quote(user)),
+            (None, _) => String::new(),
+        },
+        executable,
+        arguments.join(" ")
+    );
+    let command = match definition.user {
+        Some(ref user) => format!(
+            r#"if command -v start-stop-daemon >/dev/null 2>&1; then
+        {start_stop_daemon}
+    elif command -v setpriv >/dev/null 2>&1; then
+        setpriv --reuid={user} --regid={group} --init-groups {command_line} >/dev/null 2>&1 &
+        echo $! > "$PIDFILE"
+    else
+        echo "start-stop-daemon or setpriv is required to start $NAME"
+        return 1
+    fi"#,
+            start_stop_daemon = start_stop_daemon,
+            user = shell_quote(user),
+            group = match definition.group {
+                Some(ref group) => shell_quote(group),
+                None => format!("\"$(id -g {})\"", shell_quote(user)),
+            },
+            command_line = command_line,
+        ),
+        None => format!(
+            r#"if command -v start-stop-daemon >/dev/null 2>&1; then
+        {start_stop_daemon}
+    else
+        {command_line} >/dev/null 2>&1 &
+        echo $! > "$PIDFILE"
+    fi"#,
+            start_stop_daemon = start_stop_daemon,
+            command_line = command_line,
+        ),
+    };
+
+    let _ = write!(
+        script,
+        r#"
+is_running() {{
+    [ -f "$PIDFILE" ] && kill -0 "$(cat "$PIDFILE")" 2>/dev/null
+}}
+
+start() {{
+    if is_running; then
+        echo "$NAME is already running"
+        return 0
+    fi
+    echo "Starting $NAME"
+    {command}
+}}
+
+stop() {{
+    if ! is_running; then
+        echo "$NAME is not running"
+        rm -f "$PIDFILE"
+        return 0
+    fi
+    echo "Stopping $NAME"
+    kill "$(cat "$PIDFILE")"
+    for i in 1 2 3 4 5 6 7 8 9 10; do
+        is_running || break
+        sleep 1
+    done
+    rm -f "$PIDFILE"
+}}
+
+case "$1" in
+    start)
+        start
+        ;;
+    stop)
+        stop
+        ;;
+    restart)
+        stop
+        start
+        ;;
+    status)
+        if is_running; then
+            echo "$NAME is running"
+        else
+            echo "$NAME is not running"
+            exit 3
+        fi
+        ;;
+    *)
+        echo "Usage: $0 {{start|stop|restart|status}}"
+        exit 2
+        ;;
+esac
+"#,
+        command = command
+    );
+
+    script
+}
+
+/// Manages the service through an LSB init script, for Linux systems without systemd.
+pub struct SysvController {
+    pub service_name: String,
+    pub display_name: String,
+    pub description: String,
+    /// Environment variables exported by the init script.
+    pub environment: Vec<(String, String)>,
+    /// User the service runs as, through `start-stop-daemon --chuid` or `setpriv`. The service runs as root
+    /// when unset.
+    pub user: Option<String>,
+    /// File holding the pid of the running service, defaults to `/var/run/<service_name>.pid`.
+    pub pid_file: PathBuf,
+}
+
+impl SysvController {
+    pub fn new(service_name: &str, display_name: &str, description: &str) -> SysvController {
+        SysvController {
+            service_name: service_name.to_string(),
+            display_name: display_name.to_string(),
+            description: description.to_string(),
+            environment: Vec::new(),
+            user: None,
+            pid_file: Path::new("/var/run/").join(format!("{}.pid", service_name)),
+        }
+    }
+
+    pub fn register(
+        &mut self,
+        service_main_wrapper: LinuxServiceMainWrapperFn,
+    ) -> Result<(), Error> {
+        service_main_wrapper(env::args().collect());
+        Ok(())
+    }
+
+    fn get_init_script_path(&self) -> PathBuf {
+        Path::new("/etc/init.d/").join(&self.service_name)
+    }
+
+    fn init_script_execute(&self, command: &str) -> Result<(), Error> {
+        let path = self.get_init_script_path();
+        command_execute(
+            path.to_str().ok_or("Failed to parse init script path")?,
+            &[command],
+        )
+    }
+
+    fn write_init_script(&self) -> Result<(), Error> {
+        let mut definition = ServiceDefinition::new(
+            &self.service_name,
+            &self.display_name,
+            &self.description,
+            &current_exe()?,
+        );
+        definition.environment = self.environment.clone();
+        definition.user = self.user.clone();
+
+        let path = self.get_init_script_path();
+        let content = sysv_init_script(&definition, &self.pid_file);
+        info!("Writing init script {}", path.display());
+        File::create(&path)
+            .and_then(|mut file| file.write_all(content.as_bytes()))
+            .and_then(|_| fs::set_permissions(&path, fs::Permissions::from_mode(0o755)))
+            .map_err(|e| Error::new(&format!("Failed to write {}: {}", path.display(), e)))
+    }
+}
+
+impl ControllerInterface for SysvController {
+    fn create(&mut self) -> Result<(), Error> {
+        check_environment_names(&self.environment)?;
+        self.write_init_script()?;
+
+        sysv_register_script(&self.service_name)
+    }
+
+    fn delete(&mut self) -> Result<(), Error> {
+        self.init_script_execute("stop")
+            .map_err(|e| debug!("{}", e))
+            .ok();
+        sysv_unregister_script(&self.service_name)?;
+
+        let path = self.get_init_script_path();
+        fs::remove_file(&path)
+            .map_err(|e| debug!("Failed to delete {}: {}", path.display(), e))
+            .ok();
+
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<(), Error> {
+        self.init_script_execute("start")
+    }
+
+    fn stop(&mut self) -> Result<(), Error> {
+        self.init_script_execute("stop")
+    }
+
+    fn status(&mut self) -> Result<ServiceStatus, Error> {
+        let path = self.get_init_script_path();
+        match command_exit_code(
+            path.to_str().ok_or("Failed to parse init script path")?,
+            &["status"],
+        )? {
+            0 => Ok(ServiceStatus::Running),
+            _ => Ok(ServiceStatus::Stopped),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    fn definition() -> ServiceDefinition {
+        let mut definition =
+            ServiceDefinition::new("foobar", "FooBar", "FooBar\nservice", "/opt/foo bar/foobar");
+        definition.arguments = vec!["--name".to_string(), "it's $HOME".to_string()];
+        definition.user = Some("foobar".to_string());
+        definition
+    }
+
+    #[test]
+    fn lsb_header() {
+        let script = sysv_init_script(&definition(), Path::new("/var/run/foobar.pid"));
+        assert!(script.starts_with(
+            "#!/bin/sh\n\
+             ### BEGIN INIT INFO\n\
+             # Provides:          foobar\n\
+             # Required-Start:    $remote_fs $syslog $network\n\
+             # Required-Stop:     $remote_fs $syslog $network\n\
+             # Default-Start:     2 3 4 5\n\
+             # Default-Stop:      0 1 6\n\
+             # Short-Description: FooBar\n\
+             # Description:       FooBar service\n\
+             ### END INIT INFO\n\
+             # chkconfig: 2345 90 10\n\
+             # description: FooBar service\n\
+             \n\
+             NAME='foobar'\n\
+             PIDFILE='/var/run/foobar.pid'\n"
+        ));
+        assert!(script.contains(
+            "start-stop-daemon --start --quiet --background --make-pidfile --pidfile \"$PIDFILE\" \
+             --chuid 'foobar' --startas '/opt/foo bar/foobar' -- '--name' 'it'\\''s $HOME'\n"
+        ));
+        assert!(script.contains(
+            "setpriv --reuid='foobar' --regid=\"$(id -g 'foobar')\" --init-groups \
+             '/opt/foo bar/foobar' '--name' 'it'\\''s $HOME' >/dev/null 2>&1 &\n"
+        ));
+    }
+
+    #[test]
+    fn environment_round_trip() {
+        let values = [
+            "foo bar",
+            "it's",
+            "$HOME/${PATH}:$$",
+            "`id`",
+            "back\\slash \" mixed",
+            "multi\nline\ttab",
+        ];
+        let mut definition = definition();
+        definition.environment = values
+            .iter()
+            .enumerate()
+            .map(|(index, value)| (format!("VAR_{}", index), value.to_string()))
+            .collect();
+
+        let script = sysv_init_script(&definition, Path::new("/var/run/foobar.pid"));
+        // Everything before the functions: the LSB header, the variables and the exports.
+        let (header, _) = script.split_once("\nis_running() {").unwrap();
+
+        let names: Vec<String> = definition
+            .environment
+            .iter()
+            .map(|(name, _)| format!("\"${}\"", name))
+            .collect();
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(format!("{}\nprintf '%s\\0' {}", header, names.join(" ")))
+            .output()
+            .unwrap();
+        let output = String::from_utf8(output.stdout).unwrap();
+        let parsed: Vec<&str> = output.split_terminator('\0').collect();
+        assert_eq!(parsed, values);
+    }
+}
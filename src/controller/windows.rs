@@ -2,9 +2,11 @@ use std::ffi::OsStr;
 use std::iter::once;
 use std::os::windows::ffi::OsStrExt;
 use std::ptr;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::mpsc;
 use std::{thread, time};
 
+use log::warn;
 use widestring::WideCString;
 use winapi::shared::minwindef::*;
 use winapi::shared::winerror::*;
@@ -12,17 +14,32 @@ use winapi::um::errhandlingapi::*;
 use winapi::um::libloaderapi::*;
 use winapi::um::winbase::*;
 use winapi::um::winnt::*;
+use winapi::um::winreg::*;
 use winapi::um::winsvc::*;
 use winapi::um::winuser::*;
 use winapi::{self, STRUCT};
 
-use crate::controller::{ControllerInterface, ServiceMainFn};
+use crate::controller::{set_paused, ControllerInterface, ServiceMainFn};
+use crate::dependency::Dependency;
+use crate::logging;
+#[cfg(feature = "metrics")]
+use crate::metrics;
+use crate::restart::{Restart, RestartPolicy};
 use crate::session;
 use crate::Error;
 use crate::ServiceEvent;
 
 static mut SERVICE_CONTROL_HANDLE: SERVICE_STATUS_HANDLE = ptr::null_mut();
 
+/// Controls the service accepts by default: stopping, shutdown, pausing and session changes.
+const DEFAULT_CONTROLS_ACCEPTED: DWORD = SERVICE_ACCEPT_STOP
+    | SERVICE_ACCEPT_SHUTDOWN
+    | SERVICE_ACCEPT_PAUSE_CONTINUE
+    | SERVICE_ACCEPT_SESSIONCHANGE;
+
+/// Controls reported by `set_service_status`, set from `controls_accepted` by `register()`.
+static CONTROLS_ACCEPTED: AtomicU32 = AtomicU32::new(DEFAULT_CONTROLS_ACCEPTED);
+
 STRUCT! {#[allow(non_snake_case)]
     struct SERVICE_DESCRIPTION_W {
     lpDescription: LPWSTR,
@@ -100,12 +117,23 @@ pub struct WindowsController {
     pub error_control: DWORD,
     pub tag_id: DWORD,
     pub load_order_group: String,
-    pub dependencies: String,
+    /// Services the service requires, passed to `CreateService` (`lpDependencies`). Windows has no weak or
+    /// ordering-only dependency, such dependencies are ignored.
+    pub dependencies: Vec<Dependency>,
     pub account_name: String,
     pub password: String,
     pub service_status: SERVICE_STATUS,
     pub status_handle: SERVICE_STATUS_HANDLE,
+    /// Controls the service reports to accept once registered, `SERVICE_CONTROL_PAUSE` and
+    /// `SERVICE_CONTROL_CONTINUE` being delivered as `ServiceEvent::Pause` and `ServiceEvent::Continue`.
+    /// Defaults to stopping, shutdown, pausing and session changes.
     pub controls_accepted: DWORD,
+    /// Restart policy of the service, applied through the service failure actions.
+    pub restart: RestartPolicy,
+    /// Message file of the event log source registered by `create()`, with environment variables such as
+    /// `%SystemRoot%`. Defaults to the .NET Framework v4 event log messages, which format every event as
+    /// its message. The source is registered without a message file when the file is missing or unset.
+    pub event_message_file: Option<String>,
 }
 
 impl ControllerInterface for WindowsController {
@@ -115,6 +143,7 @@ impl ControllerInterface for WindowsController {
 
             let filename = get_filename();
             let tag_id = 0;
+            let dependencies = get_dependencies_utf16(&self.dependencies);
 
             let service = CreateServiceW(
                 service_manager.handle,
@@ -127,7 +156,9 @@ impl ControllerInterface for WindowsController {
                 get_utf16(filename.as_str()).as_ptr(),
                 ptr::null_mut(),
                 ptr::null_mut(),
-                ptr::null_mut(),
+                dependencies
+                    .as_ref()
+                    .map_or(ptr::null(), |dependencies| dependencies.as_ptr()),
                 ptr::null_mut(),
                 ptr::null_mut(),
             );
@@ -149,8 +180,16 @@ impl ControllerInterface for WindowsController {
 
             let p_sd = &mut sd as *mut _ as *mut winapi::ctypes::c_void;
             ChangeServiceConfig2W(service, SERVICE_CONFIG_DESCRIPTION, p_sd);
+            set_failure_actions(service, &self.restart);
             CloseServiceHandle(service);
 
+            // The service works without its event source, only the event viewer misses the messages.
+            if let Err(e) =
+                register_event_source(&self.service_name, self.event_message_file.as_deref())
+            {
+                warn!("{}", e);
+            }
+
             Ok(())
         }
     }
@@ -180,6 +219,7 @@ impl ControllerInterface for WindowsController {
                     get_last_error_text()
                 )));
             }
+            deregister_event_source(&self.service_name);
 
             Ok(())
         }
@@ -235,9 +275,50 @@ impl ControllerInterface for WindowsController {
             Ok(())
         }
     }
+
+    fn pause(&mut self) -> Result<(), Error> {
+        self.control(SERVICE_CONTROL_PAUSE, SERVICE_PAUSE_PENDING, SERVICE_PAUSED)
+    }
+
+    fn resume(&mut self) -> Result<(), Error> {
+        self.control(
+            SERVICE_CONTROL_CONTINUE,
+            SERVICE_CONTINUE_PENDING,
+            SERVICE_RUNNING,
+        )
+    }
 }
 
 impl WindowsController {
+    /// Sends `control` to the service and waits for it to leave the `pending` state for `target`.
+    fn control(&mut self, control: DWORD, pending: DWORD, target: DWORD) -> Result<(), Error> {
+        unsafe {
+            let service_manager = ServiceControlManager::open(SC_MANAGER_ALL_ACCESS)?;
+            let service = service_manager.open_service(&self.service_name, SERVICE_ALL_ACCESS)?;
+
+            if ControlService(service.handle, control, &mut self.service_status) == 0 {
+                return Err(Error::new(&format!(
+                    "ControlService: failed to send control {}",
+                    control
+                )));
+            }
+            while self.service_status.dwCurrentState == pending
+                && QueryServiceStatus(service.handle, &mut self.service_status) != 0
+            {
+                thread::sleep(time::Duration::from_millis(250));
+            }
+
+            if self.service_status.dwCurrentState != target {
+                return Err(Error::new(&format!(
+                    "The service did not reach state {}",
+                    target
+                )));
+            }
+
+            Ok(())
+        }
+    }
+
     pub fn new(service_name: &str, display_name: &str, description: &str) -> WindowsController {
         WindowsController {
             service_name: service_name.to_string(),
@@ -249,7 +330,7 @@ impl WindowsController {
             error_control: SERVICE_ERROR_NORMAL,
             tag_id: 0,
             load_order_group: "".to_string(),
-            dependencies: "".to_string(),
+            dependencies: Vec::new(),
             account_name: "".to_string(),
             password: "".to_string(),
             service_status: SERVICE_STATUS {
@@ -262,7 +343,9 @@ impl WindowsController {
                 dwWaitHint: 0,
             },
             status_handle: ptr::null_mut(),
-            controls_accepted: SERVICE_ACCEPT_STOP,
+            controls_accepted: DEFAULT_CONTROLS_ACCEPTED,
+            restart: RestartPolicy::default(),
+            event_message_file: Some(DEFAULT_EVENT_MESSAGE_FILE.to_string()),
         }
     }
 
@@ -271,6 +354,7 @@ impl WindowsController {
         &mut self,
         service_main_wrapper: WindowsServiceMainWrapperFn,
     ) -> Result<(), Error> {
+        CONTROLS_ACCEPTED.store(self.controls_accepted, Ordering::Relaxed);
         unsafe {
             let service_name = get_utf16(self.service_name.as_str());
 
@@ -290,6 +374,159 @@ impl WindowsController {
     }
 }
 
+/// Configures the service failure actions: the service is restarted `burst` times within `interval`, and
+/// indefinitely without a burst limit. Services always report a failure when they exit with an error, so
+/// `Restart::Always` behaves as `Restart::OnFailure` that also applies to non-crash failures.
+unsafe fn set_failure_actions(service: SC_HANDLE, policy: &RestartPolicy) {
+    if policy.restart == Restart::Never {
+        return;
+    }
+
+    let restart_action = SC_ACTION {
+        Type: SC_ACTION_RESTART,
+        Delay: policy.delay.as_millis() as DWORD,
+    };
+    let mut actions = match policy.burst {
+        Some(burst) => {
+            let mut actions = vec![restart_action; burst as usize];
+            actions.push(SC_ACTION {
+                Type: SC_ACTION_NONE,
+                Delay: 0,
+            });
+            actions
+        }
+        None => vec![restart_action],
+    };
+
+    let mut failure_actions = SERVICE_FAILURE_ACTIONSW {
+        dwResetPeriod: policy.interval.as_secs() as DWORD,
+        lpRebootMsg: ptr::null_mut(),
+        lpCommand: ptr::null_mut(),
+        cActions: actions.len() as DWORD,
+        lpsaActions: actions.as_mut_ptr(),
+    };
+    let p_fa = &mut failure_actions as *mut _ as *mut winapi::ctypes::c_void;
+    ChangeServiceConfig2W(service, SERVICE_CONFIG_FAILURE_ACTIONS, p_fa);
+
+    let mut flag = SERVICE_FAILURE_ACTIONS_FLAG {
+        fFailureActionsOnNonCrashFailures: (policy.restart == Restart::Always) as BOOL,
+    };
+    let p_flag = &mut flag as *mut _ as *mut winapi::ctypes::c_void;
+    ChangeServiceConfig2W(service, SERVICE_CONFIG_FAILURE_ACTIONS_FLAG, p_flag);
+}
+
+/// Registry key of the event log source of the service.
+fn get_event_source_key(service_name: &str) -> String {
+    format!(
+        "SYSTEM\\CurrentControlSet\\Services\\EventLog\\Application\\{}",
+        service_name
+    )
+}
+
+/// Default message file of the event log source: the .NET event log messages format every event id as
+/// its first insertion string, which is the message `EventLogger` reports.
+const DEFAULT_EVENT_MESSAGE_FILE: &str =
+    "%SystemRoot%\\Microsoft.NET\\Framework\\v4.0.30319\\EventLogMessages.dll";
+
+/// Whether the message file exists, once `%NAME%` environment variables are expanded.
+fn message_file_exists(message_file: &str) -> bool {
+    let mut path = String::new();
+    let mut parts = message_file.split('%');
+    if let Some(first) = parts.next() {
+        path.push_str(first);
+    }
+    while let Some(name) = parts.next() {
+        match (std::env::var(name), parts.next()) {
+            (Ok(value), Some(rest)) => {
+                path.push_str(&value);
+                path.push_str(rest);
+            }
+            _ => return false,
+        }
+    }
+    std::path::Path::new(&path).is_file()
+}
+
+/// Registers the service name as event log source, so the event viewer displays the messages of
+/// `EventLogger` instead of a missing description.
+unsafe fn register_event_source(
+    service_name: &str,
+    message_file: Option<&str>,
+) -> Result<(), Error> {
+    let mut key: HKEY = ptr::null_mut();
+    let status = RegCreateKeyExW(
+        HKEY_LOCAL_MACHINE,
+        get_utf16(&get_event_source_key(service_name)).as_ptr(),
+        0,
+        ptr::null_mut(),
+        REG_OPTION_NON_VOLATILE,
+        KEY_SET_VALUE,
+        ptr::null_mut(),
+        &mut key,
+        ptr::null_mut(),
+    );
+    if status != ERROR_SUCCESS as LONG {
+        return Err(Error::new(&format!(
+            "Failed to register event source {}: {}",
+            service_name,
+            std::io::Error::from_raw_os_error(status)
+        )));
+    }
+
+    let message_file = match message_file {
+        Some(message_file) if message_file_exists(message_file) => Some(get_utf16(message_file)),
+        Some(message_file) => {
+            warn!(
+                "Event message file {} not found, the event source {} is registered without it",
+                message_file, service_name
+            );
+            None
+        }
+        None => None,
+    };
+    let types_supported: DWORD =
+        (EVENTLOG_ERROR_TYPE | EVENTLOG_WARNING_TYPE | EVENTLOG_INFORMATION_TYPE) as DWORD;
+    let mut status = match message_file {
+        Some(ref message_file) => RegSetValueExW(
+            key,
+            get_utf16("EventMessageFile").as_ptr(),
+            0,
+            REG_EXPAND_SZ,
+            message_file.as_ptr() as *const BYTE,
+            (message_file.len() * 2) as DWORD,
+        ),
+        None => ERROR_SUCCESS as LONG,
+    };
+    if status == ERROR_SUCCESS as LONG {
+        status = RegSetValueExW(
+            key,
+            get_utf16("TypesSupported").as_ptr(),
+            0,
+            REG_DWORD,
+            &types_supported as *const DWORD as *const BYTE,
+            std::mem::size_of::<DWORD>() as DWORD,
+        );
+    }
+    RegCloseKey(key);
+
+    match status == ERROR_SUCCESS as LONG {
+        true => Ok(()),
+        false => Err(Error::new(&format!(
+            "Failed to register event source {}: {}",
+            service_name,
+            std::io::Error::from_raw_os_error(status)
+        ))),
+    }
+}
+
+/// Removes the event log source of the service, if it is registered.
+unsafe fn deregister_event_source(service_name: &str) {
+    RegDeleteKeyW(
+        HKEY_LOCAL_MACHINE,
+        get_utf16(&get_event_source_key(service_name)).as_ptr(),
+    );
+}
+
 fn set_service_status(
     status_handle: SERVICE_STATUS_HANDLE,
     current_state: DWORD,
@@ -298,10 +535,7 @@ fn set_service_status(
     let mut service_status = SERVICE_STATUS {
         dwServiceType: SERVICE_WIN32_OWN_PROCESS,
         dwCurrentState: current_state,
-        dwControlsAccepted: SERVICE_ACCEPT_STOP
-            | SERVICE_ACCEPT_SHUTDOWN
-            | SERVICE_ACCEPT_PAUSE_CONTINUE
-            | SERVICE_ACCEPT_SESSIONCHANGE,
+        dwControlsAccepted: CONTROLS_ACCEPTED.load(Ordering::Relaxed),
         dwWin32ExitCode: 0,
         dwServiceSpecificExitCode: 0,
         dwCheckPoint: 0,
@@ -327,11 +561,17 @@ unsafe extern "system" fn service_handler<T>(
             0
         }
         SERVICE_CONTROL_PAUSE => {
-            let _ = (*tx).send(ServiceEvent::Pause);
+            set_service_status(SERVICE_CONTROL_HANDLE, SERVICE_PAUSED, 0);
+            if set_paused(true) {
+                let _ = (*tx).send(ServiceEvent::Pause);
+            }
             0
         }
         SERVICE_CONTROL_CONTINUE => {
-            let _ = (*tx).send(ServiceEvent::Continue);
+            set_service_status(SERVICE_CONTROL_HANDLE, SERVICE_RUNNING, 0);
+            if set_paused(false) {
+                let _ = (*tx).send(ServiceEvent::Continue);
+            }
             0
         }
         SERVICE_CONTROL_SESSIONCHANGE => {
@@ -388,6 +628,20 @@ pub fn get_utf16(value: &str) -> Vec<u16> {
     OsStr::new(value).encode_wide().chain(once(0)).collect()
 }
 
+/// Double null-terminated list of the required services, for `lpDependencies`.
+fn get_dependencies_utf16(dependencies: &[Dependency]) -> Option<Vec<u16>> {
+    let mut list: Vec<u16> = dependencies
+        .iter()
+        .filter(|dependency| dependency.is_required())
+        .flat_map(|dependency| get_utf16(&dependency.name))
+        .collect();
+    if list.is_empty() {
+        return None;
+    }
+    list.push(0);
+    Some(list)
+}
+
 pub fn get_filename() -> String {
     unsafe {
         let mut filename = [0u16; MAX_PATH];
@@ -430,10 +684,21 @@ macro_rules! Service {
 }
 
 #[doc(hidden)]
-pub fn dispatch<T>(service_main: ServiceMainFn<T>, name: &str, argc: DWORD, argv: *mut LPWSTR) {
+pub fn dispatch<T: Send + 'static>(
+    service_main: ServiceMainFn<T>,
+    name: &str,
+    argc: DWORD,
+    argv: *mut LPWSTR,
+) {
+    logging::setup(name, false)
+        .map_err(|e| eprintln!("{}", e))
+        .ok();
+
     let args = get_args(argc, argv);
     let service_name = get_utf16(name);
     let (mut tx, rx) = mpsc::channel();
+    #[cfg(feature = "metrics")]
+    let rx = metrics::observe(name, rx);
     let _tx = tx.clone();
     let ctrl_handle = unsafe {
         RegisterServiceCtrlHandlerExW(
@@ -445,6 +710,8 @@ pub fn dispatch<T>(service_main: ServiceMainFn<T>, name: &str, argc: DWORD, argv
     unsafe { SERVICE_CONTROL_HANDLE = ctrl_handle };
     set_service_status(ctrl_handle, SERVICE_START_PENDING, 0);
     set_service_status(ctrl_handle, SERVICE_RUNNING, 0);
-    service_main(rx, _tx, args, false);
+    let _exit_code = service_main(rx, _tx, args, false);
+    #[cfg(feature = "metrics")]
+    metrics::record_exit_code(name, _exit_code);
     set_service_status(ctrl_handle, SERVICE_STOPPED, 0);
 }
@@ -0,0 +1,257 @@
+//! Portable service definition, rendered to the manifests of every supported service manager.
+//!
+//! Rendering does not touch the host system: the systemd units, the launchd plist and the Windows
+//! installation scripts of a service can all be generated on any platform, for instance by a packaging job.
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::dependency::Dependency;
+use crate::launchd::LaunchdPlist;
+use crate::restart::RestartPolicy;
+use crate::schedule::Schedule;
+use crate::scm;
+use crate::systemd::{self, Hardening};
+use crate::Error;
+
+/// A socket the service manager listens on, starting the service on demand.
+///
+/// The address is a port (`8080`), an address and port (`127.0.0.1:8080`, `[::1]:8080`) or the absolute
+/// path of a Unix domain socket. Windows has no socket activation, sockets are ignored there.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ListenSocket {
+    Stream(String),
+    Datagram(String),
+}
+
+/// A generated file, with a path relative to the root of the target file system.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServiceFile {
+    pub path: PathBuf,
+    pub content: String,
+}
+
+impl ServiceFile {
+    /// Writes the file under `root`, creating parent directories as needed.
+    pub fn write(&self, root: &Path) -> Result<(), Error> {
+        let path = root.join(&self.path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| {
+                Error::new(&format!("Failed to create {}: {}", parent.display(), e))
+            })?;
+        }
+        File::create(&path)
+            .and_then(|mut file| file.write_all(self.content.as_bytes()))
+            .map_err(|e| Error::new(&format!("Failed to write {}: {}", path.display(), e)))
+    }
+}
+
+/// Description of a service, independent of the platform it is installed on.
+///
+/// Settings that a service manager has no equivalent for are ignored by its renderer.
+///
+/// ```rust
+/// use std::path::Path;
+/// use ceviche::definition::{ListenSocket, ServiceDefinition};
+/// use ceviche::restart::RestartPolicy;
+///
+/// let mut definition = ServiceDefinition::new("foobar", "FooBar Service", "This is the FooBar service", "/usr/bin/foobar");
+/// definition.windows_executable = Some(r"C:\Program Files\FooBar\foobar.exe".to_string());
+/// definition.arguments = vec!["--config".to_string(), "/etc/foo bar.conf".to_string()];
+/// definition.restart = RestartPolicy::on_failure();
+/// definition.sockets.push(ListenSocket::Stream("8080".to_string()));
+///
+/// let files = definition.render_all();
+/// let paths: Vec<&Path> = files.iter().map(|file| file.path.as_path()).collect();
+/// assert_eq!(paths, [
+///     Path::new("lib/systemd/system/foobar.service"),
+///     Path::new("lib/systemd/system/foobar.socket"),
+///     Path::new("Library/LaunchDaemons/foobar.plist"),
+///     Path::new("windows/foobar-install.cmd"),
+///     Path::new("windows/foobar.wxi"),
+/// ]);
+///
+/// assert_eq!(files[0].content, "\
+/// [Unit]
+/// Description=FooBar Service
+///
+/// [Service]
+/// ExecStart=/usr/bin/foobar --config \"/etc/foo bar.conf\"
+/// Restart=on-failure
+/// RestartSec=1
+///
+/// [Install]
+/// WantedBy=multi-user.target
+/// ");
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServiceDefinition {
+    pub service_name: String,
+    pub display_name: String,
+    pub description: String,
+    /// Path of the installed executable.
+    pub executable: String,
+    /// Path of the installed executable on Windows, `executable` is used when unset.
+    pub windows_executable: Option<String>,
+    /// Arguments passed to the executable.
+    pub arguments: Vec<String>,
+    pub working_directory: Option<String>,
+    pub environment: Vec<(String, String)>,
+    /// Files the environment is read from, only supported by systemd.
+    pub environment_files: Vec<String>,
+    /// Account the service runs as, the system account when unset.
+    pub user: Option<String>,
+    pub group: Option<String>,
+    /// Additional groups of the service process, only supported by systemd.
+    pub supplementary_groups: Vec<String>,
+    /// Runs the service as a transient user, only supported by systemd.
+    pub dynamic_user: bool,
+    /// Sandboxing directives, only supported by systemd.
+    pub hardening: Hardening,
+    pub restart: RestartPolicy,
+    pub dependencies: Vec<Dependency>,
+    /// Pid file written by a service that forks into the background, only supported by systemd which
+    /// then starts the service with `Type=forking`.
+    pub pid_file: Option<String>,
+    pub sockets: Vec<ListenSocket>,
+    /// Runs the service periodically instead of keeping it running, ignored on Windows. The service main
+    /// function is expected to return once its work is done.
+    pub schedule: Option<Schedule>,
+    /// Renders template units (`<service_name>@.service`), started once per instance, only supported by
+    /// systemd.
+    pub template: bool,
+    /// Content of a drop-in added to the systemd unit.
+    pub config: Option<String>,
+}
+
+impl ServiceDefinition {
+    pub fn new(
+        service_name: &str,
+        display_name: &str,
+        description: &str,
+        executable: &str,
+    ) -> ServiceDefinition {
+        ServiceDefinition {
+            service_name: service_name.to_string(),
+            display_name: display_name.to_string(),
+            description: description.to_string(),
+            executable: executable.to_string(),
+            windows_executable: None,
+            arguments: Vec::new(),
+            working_directory: None,
+            environment: Vec::new(),
+            environment_files: Vec::new(),
+            user: None,
+            group: None,
+            supplementary_groups: Vec::new(),
+            dynamic_user: false,
+            hardening: Hardening::default(),
+            restart: RestartPolicy::default(),
+            dependencies: Vec::new(),
+            pid_file: None,
+            sockets: Vec::new(),
+            schedule: None,
+            template: false,
+            config: None,
+        }
+    }
+
+    /// Name of the systemd unit of type `unit_type`, such as `foobar.service` or `foobar@.service` for a
+    /// template.
+    pub fn systemd_unit_name(&self, unit_type: &str) -> String {
+        match self.template {
+            true => format!("{}@.{}", self.service_name, unit_type),
+            false => format!("{}.{}", self.service_name, unit_type),
+        }
+    }
+
+    /// Renders the systemd service unit, the socket unit when the service has sockets, the timer unit when
+    /// it has a schedule and the drop-in holding `config`.
+    ///
+    /// ```rust
+    /// use std::path::Path;
+    /// use ceviche::definition::ServiceDefinition;
+    ///
+    /// let mut definition = ServiceDefinition::new("gateway", "Gateway", "API gateway", "/usr/bin/gateway");
+    /// definition.template = true;
+    ///
+    /// let files = definition.render_systemd();
+    /// assert_eq!(files[0].path, Path::new("lib/systemd/system/gateway@.service"));
+    /// assert!(files[0].content.contains("Environment=\"CEVICHE_INSTANCE=%I\"\n"));
+    /// ```
+    pub fn render_systemd(&self) -> Vec<ServiceFile> {
+        let unit_dir = Path::new("lib/systemd/system");
+        let mut files = vec![ServiceFile {
+            path: unit_dir.join(self.systemd_unit_name("service")),
+            content: systemd::service_unit(self).render(),
+        }];
+
+        if let Some(unit) = systemd::socket_unit(self) {
+            files.push(ServiceFile {
+                path: unit_dir.join(self.systemd_unit_name("socket")),
+                content: unit.render(),
+            });
+        }
+
+        if let Some(unit) = systemd::timer_unit(self) {
+            files.push(ServiceFile {
+                path: unit_dir.join(self.systemd_unit_name("timer")),
+                content: unit.render(),
+            });
+        }
+
+        if let Some(ref config) = self.config {
+            files.push(ServiceFile {
+                path: unit_dir
+                    .join(format!("{}.d", self.systemd_unit_name("service")))
+                    .join(format!("{}.conf", self.service_name)),
+                content: config.to_string(),
+            });
+        }
+
+        files
+    }
+
+    /// Renders the launchd daemon plist.
+    pub fn render_launchd(&self) -> ServiceFile {
+        ServiceFile {
+            path: Path::new("Library/LaunchDaemons").join(format!("{}.plist", self.service_name)),
+            content: LaunchdPlist::from_definition(self).render(),
+        }
+    }
+
+    /// Renders a batch script installing the Windows service with `sc.exe`.
+    pub fn render_sc_script(&self) -> ServiceFile {
+        ServiceFile {
+            path: Path::new("windows").join(format!("{}-install.cmd", self.service_name)),
+            content: scm::sc_script(self),
+        }
+    }
+
+    /// Renders a WiX include holding the `ServiceInstall` and `ServiceControl` elements of the service,
+    /// to be included in the component installing the executable.
+    pub fn render_wix(&self) -> ServiceFile {
+        ServiceFile {
+            path: Path::new("windows").join(format!("{}.wxi", self.service_name)),
+            content: scm::wix_include(self),
+        }
+    }
+
+    /// Renders the artifacts of every service manager.
+    pub fn render_all(&self) -> Vec<ServiceFile> {
+        let mut files = self.render_systemd();
+        files.push(self.render_launchd());
+        files.push(self.render_sc_script());
+        files.push(self.render_wix());
+        files
+    }
+
+    /// Renders the artifacts of every service manager and writes them under `root`.
+    pub fn write_all(&self, root: &Path) -> Result<(), Error> {
+        for file in self.render_all() {
+            file.write(root)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,72 @@
+//! Portable service dependencies, applied by every controller when the service is created.
+//!
+//! Dependencies map to `Requires=`/`Wants=` and `After=`/`Before=` on Linux, to the service dependencies
+//! (`lpDependencies`) on Windows and to `KeepAlive` `OtherJobEnabled` on macOS, for jobs launchd keeps
+//! alive. Each service manager has its own naming scheme, the name of a dependency is used as is on
+//! Windows and macOS.
+
+/// How strongly the service depends on another one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Strength {
+    /// The dependency is started with the service, which is stopped if the dependency fails or is stopped.
+    Requires,
+    /// The dependency is started with the service, which keeps running if the dependency fails. Ignored on
+    /// Windows and macOS.
+    Wants,
+}
+
+/// Start order of the service relative to another one, stop order is the reverse.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Order {
+    /// The service starts once the dependency has started.
+    After,
+    /// The service starts before the dependency. Ignored on Windows and macOS.
+    Before,
+}
+
+/// A dependency of the service on another service, or on a systemd target such as `network-online.target`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dependency {
+    /// Name of the other service. systemd unit names without a unit type get the `.service` suffix.
+    pub name: String,
+    /// Strength of the dependency, the dependency only orders the services when `None`.
+    pub strength: Option<Strength>,
+    /// Order of the services, they are started in parallel when `None`.
+    pub order: Option<Order>,
+}
+
+impl Dependency {
+    pub fn new(name: &str, strength: Option<Strength>, order: Option<Order>) -> Dependency {
+        Dependency {
+            name: name.to_string(),
+            strength,
+            order,
+        }
+    }
+
+    /// Requires `name` and starts after it.
+    pub fn requires(name: &str) -> Dependency {
+        Dependency::new(name, Some(Strength::Requires), Some(Order::After))
+    }
+
+    /// Wants `name` and starts after it.
+    pub fn wants(name: &str) -> Dependency {
+        Dependency::new(name, Some(Strength::Wants), Some(Order::After))
+    }
+
+    /// Starts after `name` when both are started, without starting it.
+    pub fn after(name: &str) -> Dependency {
+        Dependency::new(name, None, Some(Order::After))
+    }
+
+    /// Starts before `name` when both are started, without starting it.
+    pub fn before(name: &str) -> Dependency {
+        Dependency::new(name, None, Some(Order::Before))
+    }
+
+    /// Whether the service cannot run without the dependency, which is the only kind of dependency
+    /// Windows and launchd support.
+    pub fn is_required(&self) -> bool {
+        self.strength == Some(Strength::Requires)
+    }
+}
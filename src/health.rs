@@ -0,0 +1,203 @@
+//! Health check endpoint for orchestrators and load balancers.
+//!
+//! A small HTTP server answers probes from the state the service main function updates through a
+//! `HealthHandle`:
+//!
+//! - `/healthz`: `200` while the service is live, `503` otherwise.
+//! - `/readyz`: `200` while the service is ready and not paused, `503` otherwise.
+//! - `/status`: JSON with the uptime in seconds, the last `ServiceEvent` and the pause state.
+
+use std::net::{SocketAddr, ToSocketAddrs};
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use crate::http::{Listener, Response};
+use crate::Error;
+use crate::ServiceEvent;
+
+/// Health of the service, as reported by `/status`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Status {
+    pub live: bool,
+    pub ready: bool,
+    pub paused: bool,
+    /// Seconds since the handle was created.
+    pub uptime: u64,
+    pub last_event: Option<String>,
+}
+
+struct State {
+    live: bool,
+    ready: bool,
+    paused: bool,
+    started: Instant,
+    last_event: Option<String>,
+}
+
+/// Shared health state, updated by the service and read by the `HealthServer`.
+#[derive(Clone)]
+pub struct HealthHandle {
+    state: Arc<Mutex<State>>,
+}
+
+impl HealthHandle {
+    /// A live service that is not ready yet.
+    pub fn new() -> HealthHandle {
+        HealthHandle {
+            state: Arc::new(Mutex::new(State {
+                live: true,
+                ready: false,
+                paused: false,
+                started: Instant::now(),
+                last_event: None,
+            })),
+        }
+    }
+
+    fn update<F: FnOnce(&mut State)>(&self, update: F) {
+        if let Ok(mut state) = self.state.lock() {
+            update(&mut state);
+        }
+    }
+
+    pub fn set_live(&self, live: bool) {
+        self.update(|state| state.live = live);
+    }
+
+    pub fn set_ready(&self, ready: bool) {
+        self.update(|state| state.ready = ready);
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.update(|state| state.paused = paused);
+    }
+
+    /// Records `event` as the last event received, `Pause` and `Continue` also update the pause state.
+    pub fn record_event<T>(&self, event: &ServiceEvent<T>) {
+        self.update(|state| {
+            match event {
+                ServiceEvent::Pause => state.paused = true,
+                ServiceEvent::Continue => state.paused = false,
+                _ => (),
+            }
+            state.last_event = Some(event.to_string());
+        });
+    }
+
+    pub fn status(&self) -> Status {
+        match self.state.lock() {
+            Ok(state) => Status {
+                live: state.live,
+                ready: state.ready,
+                paused: state.paused,
+                uptime: state.started.elapsed().as_secs(),
+                last_event: state.last_event.clone(),
+            },
+            Err(_) => Status {
+                live: false,
+                ready: false,
+                paused: false,
+                uptime: 0,
+                last_event: None,
+            },
+        }
+    }
+}
+
+impl Default for HealthHandle {
+    fn default() -> HealthHandle {
+        HealthHandle::new()
+    }
+}
+
+/// HTTP server answering health probes.
+///
+/// ```rust
+/// use std::io::{Read, Write};
+/// use std::net::TcpStream;
+/// use ceviche::health::{HealthHandle, HealthServer};
+/// use ceviche::ServiceEvent;
+///
+/// fn get(address: std::net::SocketAddr, path: &str) -> String {
+///     let mut stream = TcpStream::connect(address).unwrap();
+///     write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+///     let mut response = String::new();
+///     stream.read_to_string(&mut response).unwrap();
+///     response
+/// }
+///
+/// let server = HealthServer::bind("127.0.0.1:0").unwrap();
+/// let address = server.local_addr().unwrap();
+/// let health = HealthHandle::new();
+/// server.serve(health.clone());
+///
+/// assert!(get(address, "/healthz").starts_with("HTTP/1.1 200 OK\r\n"));
+/// assert!(get(address, "/readyz").starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+///
+/// health.set_ready(true);
+/// health.record_event(&ServiceEvent::<()>::Pause);
+/// assert!(get(address, "/readyz").starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+/// let status = get(address, "/status");
+/// assert!(status.contains(r#""paused":true"#) && status.contains(r#""last_event":"Pause""#));
+///
+/// health.record_event(&ServiceEvent::<()>::Continue);
+/// assert!(get(address, "/readyz").starts_with("HTTP/1.1 200 OK\r\n"));
+/// assert!(get(address, "/metrics").starts_with("HTTP/1.1 404 Not Found\r\n"));
+/// ```
+pub struct HealthServer {
+    listener: Listener,
+}
+
+impl HealthServer {
+    /// Listens on a TCP address such as `127.0.0.1:8080`.
+    pub fn bind<A: ToSocketAddrs>(address: A) -> Result<HealthServer, Error> {
+        Ok(HealthServer {
+            listener: Listener::bind(address)?,
+        })
+    }
+
+    /// Listens on a Unix domain socket, replacing a socket left behind by a service that is not
+    /// running anymore.
+    #[cfg(unix)]
+    pub fn bind_unix(path: &Path) -> Result<HealthServer, Error> {
+        Ok(HealthServer {
+            listener: Listener::bind_unix(path)?,
+        })
+    }
+
+    /// Address of the TCP listener, to find the port chosen by the system when binding port 0.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Path of the Unix domain socket.
+    #[cfg(unix)]
+    pub fn path(&self) -> Option<PathBuf> {
+        self.listener.path()
+    }
+
+    /// Answers probes from a background thread, serving each connection on its own thread.
+    pub fn serve(self, health: HealthHandle) {
+        self.listener.serve(move |path| respond(&health, path));
+    }
+}
+
+fn respond(health: &HealthHandle, path: &str) -> Response {
+    let status = health.status();
+    match path {
+        "/healthz" if status.live => Response::text("200 OK", "ok"),
+        "/healthz" => Response::text("503 Service Unavailable", "not live"),
+        "/readyz" if status.ready && !status.paused => Response::text("200 OK", "ok"),
+        "/readyz" => Response::text("503 Service Unavailable", "not ready"),
+        "/status" => Response::new(
+            "200 OK",
+            "application/json",
+            serde_json::to_string(&status).unwrap_or_default(),
+        ),
+        _ => Response::text("404 Not Found", "not found"),
+    }
+}
@@ -0,0 +1,161 @@
+//! Minimal HTTP/1.1 server answering the `GET` probes of the health and metrics endpoints.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use log::debug;
+
+use crate::Error;
+
+/// How long a client has to send its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub(crate) struct Response {
+    pub status: &'static str,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    pub fn new(status: &'static str, content_type: &'static str, body: String) -> Response {
+        Response {
+            status,
+            content_type,
+            body,
+        }
+    }
+
+    pub fn text(status: &'static str, body: &str) -> Response {
+        Response::new(status, "text/plain", format!("{}\n", body))
+    }
+}
+
+pub(crate) enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+impl Listener {
+    pub fn bind<A: ToSocketAddrs>(address: A) -> Result<Listener, Error> {
+        TcpListener::bind(address)
+            .map(Listener::Tcp)
+            .map_err(|e| Error::new(&format!("Failed to bind HTTP endpoint: {}", e)))
+    }
+
+    /// Listens on a Unix domain socket, replacing a socket left behind by a service that is not
+    /// running anymore.
+    #[cfg(unix)]
+    pub fn bind_unix(path: &Path) -> Result<Listener, Error> {
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(Error::new(&format!(
+                    "Socket {} is already in use",
+                    path.display()
+                )));
+            }
+            std::fs::remove_file(path)
+                .map_err(|e| Error::new(&format!("Failed to delete {}: {}", path.display(), e)))?;
+        }
+
+        UnixListener::bind(path)
+            .map(Listener::Unix)
+            .map_err(|e| Error::new(&format!("Failed to bind {}: {}", path.display(), e)))
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Listener::Tcp(listener) => listener.local_addr().ok(),
+            #[cfg(unix)]
+            Listener::Unix(_) => None,
+        }
+    }
+
+    #[cfg(unix)]
+    pub fn path(&self) -> Option<PathBuf> {
+        match self {
+            Listener::Tcp(_) => None,
+            Listener::Unix(listener) => listener
+                .local_addr()
+                .ok()
+                .and_then(|address| address.as_pathname().map(Path::to_path_buf)),
+        }
+    }
+
+    /// Answers requests from a background thread, serving each connection on its own thread so that an
+    /// idle client does not delay the probes. `handler` is called with the path of each `GET` or `HEAD`
+    /// request, without its query string.
+    pub fn serve<F: Fn(&str) -> Response + Send + Sync + 'static>(self, handler: F) {
+        let handler = Arc::new(handler);
+        thread::spawn(move || match self {
+            Listener::Tcp(listener) => {
+                for stream in listener.incoming() {
+                    match stream {
+                        Ok(stream) => {
+                            let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+                            let handler = handler.clone();
+                            thread::spawn(move || serve_client(stream, &*handler));
+                        }
+                        Err(e) => debug!("Failed to accept HTTP client: {}", e),
+                    }
+                }
+            }
+            #[cfg(unix)]
+            Listener::Unix(listener) => {
+                for stream in listener.incoming() {
+                    match stream {
+                        Ok(stream) => {
+                            let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+                            let handler = handler.clone();
+                            thread::spawn(move || serve_client(stream, &*handler));
+                        }
+                        Err(e) => debug!("Failed to accept HTTP client: {}", e),
+                    }
+                }
+            }
+        });
+    }
+}
+
+fn serve_client<S: Read + Write, F: Fn(&str) -> Response>(stream: S, handler: &F) {
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    if let Err(e) = reader.read_line(&mut request_line) {
+        debug!("Failed to read from HTTP client: {}", e);
+        return;
+    }
+    // Headers are not used, but read so that the client does not see a reset connection.
+    let mut header = String::new();
+    while reader.read_line(&mut header).is_ok_and(|size| size > 2) {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+    let response = match method {
+        "GET" | "HEAD" => handler(path.split('?').next().unwrap_or_default()),
+        _ => Response::text("405 Method Not Allowed", "method not allowed"),
+    };
+
+    let mut stream = reader.into_inner();
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+    if method != "HEAD" {
+        let _ = stream.write_all(response.body.as_bytes());
+    }
+    let _ = stream.flush();
+}
@@ -0,0 +1,288 @@
+//! Single-instance guard keyed on the service name.
+//!
+//! The running instance holds an exclusive `flock` on `/run/lock/<service_name>.lock`, which the kernel
+//! releases when the process exits, even when it crashes. The lock file is never removed: a new instance
+//! always locks the file the running one holds. A second instance fails to take the lock: it can either
+//! exit, or forward its arguments to the running instance through the socket
+//! `/run/lock/<service_name>.<uid>.sock`, which only accepts clients running as the same user as the
+//! service.
+//!
+//! The lock file is the same for every user and mode, so a service started in standalone mode by a user
+//! and the instance run by the service manager exclude each other. Forwarding arguments only works
+//! between instances running as the same user.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use log::debug;
+
+use crate::socket::{bind_private, peer_uid};
+use crate::Error;
+use crate::ServiceEvent;
+
+/// How long a forwarding instance has to send its arguments.
+const READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Directory of the lock files and sockets of the instances, shared by all users.
+pub fn default_dir() -> PathBuf {
+    PathBuf::from("/run/lock")
+}
+
+/// Socket of the instance of `service_name` running as the current user.
+fn socket_path(dir: &Path, service_name: &str) -> PathBuf {
+    dir.join(format!("{}.{}.sock", service_name, unsafe {
+        libc::geteuid()
+    }))
+}
+
+/// Opens the lock file of the service, readable by every user so that instances running as another
+/// user can lock it too. A lock file owned by another user is opened read-only, which `flock` allows.
+fn open_lock(path: &Path) -> io::Result<File> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .mode(0o644)
+        .open(path);
+    match file {
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => File::open(path),
+        file => file,
+    }
+}
+
+/// Lock held by the running instance of a service, released when dropped.
+///
+/// ```rust
+/// use std::sync::mpsc;
+/// use ceviche::instance::SingleInstance;
+/// use ceviche::ServiceEvent;
+///
+/// let dir = std::env::temp_dir().join(format!("ceviche-doctest-instance-{}", std::process::id()));
+/// let instance = SingleInstance::acquire_in(&dir, "foobar").unwrap();
+/// assert!(SingleInstance::acquire_in(&dir, "foobar").is_err());
+///
+/// let (tx, rx) = mpsc::channel();
+/// instance.forward_events(tx, |args| args.join(" "));
+/// SingleInstance::forward_in(&dir, "foobar", &["open".to_string(), "foo.txt".to_string()]).unwrap();
+/// match rx.recv().unwrap() {
+///     ServiceEvent::Custom(args) => assert_eq!(args, "open foo.txt"),
+///     _ => panic!("unexpected event"),
+/// }
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub struct SingleInstance {
+    service_name: String,
+    _lock: File,
+    listener: UnixListener,
+}
+
+impl SingleInstance {
+    /// Acquires the lock of `service_name` in `default_dir()`, failing when another instance of the
+    /// service holds it.
+    pub fn acquire(service_name: &str) -> Result<SingleInstance, Error> {
+        SingleInstance::acquire_in(&default_dir(), service_name)
+    }
+
+    /// Acquires the lock of `service_name` in `dir`, failing when another instance of the service holds it.
+    pub fn acquire_in(dir: &Path, service_name: &str) -> Result<SingleInstance, Error> {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| Error::new(&format!("Failed to create {}: {}", dir.display(), e)))?;
+
+        let path = dir.join(format!("{}.lock", service_name));
+        let lock = open_lock(&path)
+            .map_err(|e| Error::new(&format!("Failed to open {}: {}", path.display(), e)))?;
+        if unsafe { libc::flock(lock.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            return Err(Error::new(&format!(
+                "Another instance of {} is already running",
+                service_name
+            )));
+        }
+
+        // The lock is held, a socket left behind by a previous instance is replaced.
+        let listener = bind_private(&socket_path(dir, service_name))?;
+
+        Ok(SingleInstance {
+            service_name: service_name.to_string(),
+            _lock: lock,
+            listener,
+        })
+    }
+
+    /// Sends `args` to the running instance of `service_name` in `default_dir()`.
+    pub fn forward(service_name: &str, args: &[String]) -> Result<(), Error> {
+        SingleInstance::forward_in(&default_dir(), service_name, args)
+    }
+
+    /// Sends `args` to the running instance of `service_name` in `dir`.
+    pub fn forward_in(dir: &Path, service_name: &str, args: &[String]) -> Result<(), Error> {
+        let mut stream = UnixStream::connect(socket_path(dir, service_name)).map_err(|e| {
+            Error::new(&format!(
+                "Failed to connect to the running instance of {}: {}",
+                service_name, e
+            ))
+        })?;
+        stream.write_all(args.join("\0").as_bytes()).map_err(|e| {
+            Error::new(&format!(
+                "Failed to forward arguments to the running instance of {}: {}",
+                service_name, e
+            ))
+        })
+    }
+
+    /// Keeps the lock until the process exits and sends the arguments forwarded by other instances to
+    /// `tx`, as `ServiceEvent::Custom` events built by `custom_event`. Clients running as another user
+    /// are rejected.
+    pub fn forward_events<T: Send + 'static>(
+        self,
+        tx: mpsc::Sender<ServiceEvent<T>>,
+        custom_event: fn(Vec<String>) -> T,
+    ) {
+        thread::spawn(move || {
+            let uid = unsafe { libc::geteuid() };
+            for stream in self.listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        debug!("Failed to accept instance of {}: {}", self.service_name, e);
+                        continue;
+                    }
+                };
+                match peer_uid(&stream) {
+                    Ok(peer_uid) if peer_uid == uid => {}
+                    Ok(peer_uid) => {
+                        debug!(
+                            "Rejected instance of {} running as user {}",
+                            self.service_name, peer_uid
+                        );
+                        continue;
+                    }
+                    Err(e) => {
+                        debug!(
+                            "Failed to identify instance of {}: {}",
+                            self.service_name, e
+                        );
+                        continue;
+                    }
+                }
+
+                let tx = tx.clone();
+                let service_name = self.service_name.clone();
+                thread::spawn(move || serve_instance(stream, &service_name, tx, custom_event));
+            }
+        });
+    }
+}
+
+fn serve_instance<T>(
+    mut stream: UnixStream,
+    service_name: &str,
+    tx: mpsc::Sender<ServiceEvent<T>>,
+    custom_event: fn(Vec<String>) -> T,
+) {
+    let mut content = String::new();
+    if let Err(e) = stream
+        .set_read_timeout(Some(READ_TIMEOUT))
+        .and_then(|_| stream.read_to_string(&mut content))
+    {
+        debug!(
+            "Failed to read arguments forwarded to {}: {}",
+            service_name, e
+        );
+        return;
+    }
+
+    let args = match content.is_empty() {
+        true => Vec::new(),
+        false => content.split('\0').map(|arg| arg.to_string()).collect(),
+    };
+    let _ = tx.send(ServiceEvent::Custom(custom_event(args)));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Command, Stdio};
+
+    use super::*;
+
+    const CHILD_ENV: &str = "CEVICHE_INSTANCE_TEST";
+
+    /// Runs `child_instance` in a new process with its own `XDG_RUNTIME_DIR`.
+    fn spawn_child(service_name: &str, runtime_dir: &Path) -> std::process::Child {
+        Command::new(env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "instance::tests::child_instance",
+                "--nocapture",
+                "-q",
+            ])
+            .env(CHILD_ENV, service_name)
+            .env("XDG_RUNTIME_DIR", runtime_dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap()
+    }
+
+    fn read_state(child: &mut std::process::Child) -> String {
+        let stdout = child.stdout.as_mut().unwrap();
+        let mut reader = BufReader::new(stdout);
+        loop {
+            let mut line = String::new();
+            assert_ne!(reader.read_line(&mut line).unwrap(), 0, "child exited");
+            if let Some(state) = line.trim().strip_prefix("instance: ") {
+                return state.to_string();
+            }
+        }
+    }
+
+    /// Takes the lock of the service named in `CHILD_ENV`, reports whether it got it and holds it until
+    /// its standard input is closed. Does nothing when run by the test harness itself.
+    #[test]
+    fn child_instance() {
+        let service_name = match env::var(CHILD_ENV) {
+            Ok(service_name) => service_name,
+            Err(_) => return,
+        };
+        let instance = SingleInstance::acquire(&service_name);
+        println!(
+            "instance: {}",
+            if instance.is_ok() { "acquired" } else { "busy" }
+        );
+        let _ = io::stdin().read_to_end(&mut Vec::new());
+    }
+
+    #[test]
+    fn lock_ignores_runtime_dir() {
+        let service_name = format!("ceviche-test-{}", std::process::id());
+        let temp_dir = env::temp_dir();
+
+        let mut first = spawn_child(&service_name, &temp_dir.join("user-a"));
+        assert_eq!(read_state(&mut first), "acquired");
+        let mut second = spawn_child(&service_name, &temp_dir.join("user-b"));
+        assert_eq!(read_state(&mut second), "busy");
+
+        drop(second.stdin.take());
+        second.wait().unwrap();
+        drop(first.stdin.take());
+        first.wait().unwrap();
+
+        // The lock is released with the process holding it.
+        let mut third = spawn_child(&service_name, &temp_dir.join("user-b"));
+        assert_eq!(read_state(&mut third), "acquired");
+        drop(third.stdin.take());
+        third.wait().unwrap();
+
+        let _ = std::fs::remove_file(default_dir().join(format!("{}.lock", service_name)));
+        let _ = std::fs::remove_file(socket_path(&default_dir(), &service_name));
+    }
+}
@@ -0,0 +1,753 @@
+//! Rendering of launchd property lists.
+//!
+//! This module does not depend on the host system, plists can be generated and inspected on any platform.
+
+use std::fmt::Write;
+
+use crate::definition::{ListenSocket, ServiceDefinition};
+use crate::dependency::Dependency;
+use crate::restart::{Restart, RestartPolicy};
+use crate::schedule::CalendarInterval;
+use crate::Error;
+
+/// A property list value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Value>),
+    /// Dictionary entries, in insertion order.
+    Dictionary(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Renders the value as a property list XML document.
+    pub fn to_xml(&self) -> String {
+        let mut xml = String::from(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+"#,
+        );
+        self.write_xml(&mut xml, 0);
+        xml.push_str("</plist>\n");
+        xml
+    }
+
+    /// Parses a property list XML document.
+    ///
+    /// Only the value types of `Value` are supported, which covers the plists generated by this module.
+    ///
+    /// ```rust
+    /// use ceviche::launchd::{KeepAlive, KeepAliveConditions, LaunchdPlist, LaunchdSocket, Value};
+    ///
+    /// let mut plist = LaunchdPlist::new("com.example.foobar", vec!["/usr/local/bin/foobar".to_string(), "--port=8080".to_string()]);
+    /// plist.standard_out_path = Some("/var/log/foobar.log".to_string());
+    /// plist.standard_error_path = Some("/var/log/foobar.err".to_string());
+    /// plist.start_interval = Some(3600);
+    /// plist.sockets.push(("Listeners".to_string(), LaunchdSocket {
+    ///     service_name: Some("8080".to_string()),
+    ///     sock_type: Some("stream".to_string()),
+    ///     ..Default::default()
+    /// }));
+    /// plist.keep_alive = Some(KeepAlive::Conditions(KeepAliveConditions {
+    ///     network_state: Some(true),
+    ///     path_state: vec![("/etc/foobar.conf".to_string(), true)],
+    ///     other_job_enabled: vec![("com.example.database".to_string(), true)],
+    ///     ..Default::default()
+    /// }));
+    ///
+    /// let value = Value::from_xml(&plist.render()).unwrap();
+    /// assert_eq!(value, plist.to_value());
+    /// assert_eq!(Value::from_xml(&value.to_xml()).unwrap(), value);
+    /// ```
+    pub fn from_xml(xml: &str) -> Result<Value, Error> {
+        let mut parser = Parser { input: xml };
+        parser.skip_prolog();
+        parser.expect_open("plist")?;
+        let value = parser.parse_value()?;
+        parser.expect_close("plist")?;
+        Ok(value)
+    }
+
+    fn write_xml(&self, xml: &mut String, depth: usize) {
+        let indent = "\t".repeat(depth);
+        match self {
+            Value::String(value) => {
+                let _ = writeln!(xml, "{}<string>{}</string>", indent, xml_escape(value));
+            }
+            Value::Integer(value) => {
+                let _ = writeln!(xml, "{}<integer>{}</integer>", indent, value);
+            }
+            Value::Boolean(value) => {
+                let _ = writeln!(xml, "{}<{}/>", indent, value);
+            }
+            Value::Array(values) => {
+                let _ = writeln!(xml, "{}<array>", indent);
+                for value in values {
+                    value.write_xml(xml, depth + 1);
+                }
+                let _ = writeln!(xml, "{}</array>", indent);
+            }
+            Value::Dictionary(entries) => {
+                let _ = writeln!(xml, "{}<dict>", indent);
+                for (key, value) in entries {
+                    let _ = writeln!(xml, "{}\t<key>{}</key>", indent, xml_escape(key));
+                    value.write_xml(xml, depth + 1);
+                }
+                let _ = writeln!(xml, "{}</dict>", indent);
+            }
+        }
+    }
+}
+
+/// Conditions under which launchd keeps the job alive.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct KeepAliveConditions {
+    /// Restarts the job when it exits successfully (`true`) or with an error (`false`).
+    pub successful_exit: Option<bool>,
+    /// Restarts the job when it crashed (`true`) or when it did not (`false`).
+    pub crashed: Option<bool>,
+    /// Keeps the job alive while the network is up (`true`) or down (`false`).
+    pub network_state: Option<bool>,
+    /// Keeps the job alive while each path exists (`true`) or does not exist (`false`).
+    pub path_state: Vec<(String, bool)>,
+    /// Keeps the job alive while each job is loaded (`true`) or not loaded (`false`).
+    pub other_job_enabled: Vec<(String, bool)>,
+}
+
+/// Value of the `KeepAlive` key.
+#[derive(Clone, Debug, PartialEq)]
+pub enum KeepAlive {
+    Always,
+    Conditions(KeepAliveConditions),
+}
+
+/// An entry of the `Sockets` dictionary, launchd listens on the socket and starts the job on demand.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LaunchdSocket {
+    /// `SockNodeName`, the address to listen on.
+    pub node_name: Option<String>,
+    /// `SockServiceName`, the port or service name to listen on.
+    pub service_name: Option<String>,
+    /// `SockPathName`, the path of a Unix domain socket.
+    pub path_name: Option<String>,
+    /// `SockType`: `stream`, `dgram` or `seqpacket`.
+    pub sock_type: Option<String>,
+    /// `SockFamily`: `IPv4`, `IPv6` or `Unix`.
+    pub family: Option<String>,
+}
+
+/// A launchd job definition.
+///
+/// ```rust
+/// use ceviche::launchd::{KeepAlive, LaunchdPlist, Value};
+///
+/// let mut plist = LaunchdPlist::new("com.example.foo&bar", vec!["/usr/local/bin/foobar".to_string()]);
+/// plist.environment_variables.push(("GREETING".to_string(), "<hello>".to_string()));
+/// plist.user_name = Some("_foobar".to_string());
+/// plist.keep_alive = Some(KeepAlive::Always);
+///
+/// let xml = plist.render();
+/// assert!(xml.contains("<string>com.example.foo&amp;bar</string>"));
+/// assert!(xml.contains("<string>&lt;hello&gt;</string>"));
+/// assert_eq!(Value::from_xml(&xml).unwrap(), plist.to_value());
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LaunchdPlist {
+    /// `Label`
+    pub label: String,
+    /// `Disabled`
+    pub disabled: bool,
+    /// `ProgramArguments`, the executable followed by its arguments.
+    pub program_arguments: Vec<String>,
+    /// `WorkingDirectory`
+    pub working_directory: Option<String>,
+    /// `RunAtLoad`
+    pub run_at_load: bool,
+    /// `EnvironmentVariables`
+    pub environment_variables: Vec<(String, String)>,
+    /// `StandardOutPath`
+    pub standard_out_path: Option<String>,
+    /// `StandardErrorPath`
+    pub standard_error_path: Option<String>,
+    /// `UserName`
+    pub user_name: Option<String>,
+    /// `GroupName`
+    pub group_name: Option<String>,
+    /// `StartInterval`, in seconds.
+    pub start_interval: Option<u32>,
+    /// `StartCalendarInterval`
+    pub start_calendar_interval: Vec<CalendarInterval>,
+    /// `LimitLoadToSessionType`
+    pub limit_load_to_session_type: Vec<String>,
+    /// `Sockets`, by name.
+    pub sockets: Vec<(String, LaunchdSocket)>,
+    /// `KeepAlive`
+    pub keep_alive: Option<KeepAlive>,
+    /// `ThrottleInterval`, in seconds.
+    pub throttle_interval: Option<u32>,
+}
+
+impl LaunchdPlist {
+    pub fn new(label: &str, program_arguments: Vec<String>) -> LaunchdPlist {
+        LaunchdPlist {
+            label: label.to_string(),
+            program_arguments,
+            run_at_load: true,
+            ..LaunchdPlist::default()
+        }
+    }
+
+    /// Builds the job definition of a daemon running `definition`.
+    ///
+    /// A scheduled service is started at its calendar events and intervals instead of being kept alive.
+    ///
+    /// ```rust
+    /// use ceviche::definition::ServiceDefinition;
+    /// use ceviche::launchd::LaunchdPlist;
+    /// use ceviche::schedule::{CalendarInterval, Schedule};
+    ///
+    /// let mut definition = ServiceDefinition::new("cleanup", "Cleanup", "Removes stale files", "/usr/local/bin/cleanup");
+    /// definition.schedule = Some(Schedule::calendar(vec![CalendarInterval::weekly(0, 3, 30)]));
+    ///
+    /// let plist = LaunchdPlist::from_definition(&definition);
+    /// assert!(!plist.run_at_load);
+    /// assert_eq!(plist.keep_alive, None);
+    /// assert!(plist.render().contains("\
+    /// \t<key>StartCalendarInterval</key>
+    /// \t<dict>
+    /// \t\t<key>Weekday</key>
+    /// \t\t<integer>0</integer>
+    /// \t\t<key>Hour</key>
+    /// \t\t<integer>3</integer>
+    /// \t\t<key>Minute</key>
+    /// \t\t<integer>30</integer>
+    /// \t</dict>
+    /// "));
+    /// ```
+    pub fn from_definition(definition: &ServiceDefinition) -> LaunchdPlist {
+        let program_arguments = std::iter::once(&definition.executable)
+            .chain(definition.arguments.iter())
+            .map(|arg| arg.to_string())
+            .collect();
+
+        let mut plist = LaunchdPlist::new(&definition.service_name, program_arguments);
+        plist.working_directory = definition.working_directory.clone();
+        plist.environment_variables = definition.environment.clone();
+        plist.user_name = definition.user.clone();
+        plist.group_name = definition.group.clone();
+        plist.sockets = definition
+            .sockets
+            .iter()
+            .enumerate()
+            .map(|(index, socket)| (format!("Listener{}", index), LaunchdSocket::from(socket)))
+            .collect();
+        match definition.schedule {
+            Some(ref schedule) => {
+                plist.run_at_load = schedule.on_boot.is_some();
+                plist.start_interval = schedule.interval.map(|interval| interval.as_secs() as u32);
+                plist.start_calendar_interval = schedule.calendar.clone();
+            }
+            None => {
+                plist.set_restart_policy(&definition.restart);
+                plist.add_dependencies(&definition.dependencies);
+            }
+        }
+        plist
+    }
+
+    /// Converts the job definition to a property list dictionary.
+    pub fn to_value(&self) -> Value {
+        let mut dict = Vec::new();
+        let string = |value: &str| Value::String(value.to_string());
+        let strings = |values: &[String]| Value::Array(values.iter().map(|v| string(v)).collect());
+
+        dict.push(("Disabled".to_string(), Value::Boolean(self.disabled)));
+        dict.push(("Label".to_string(), string(&self.label)));
+        dict.push((
+            "ProgramArguments".to_string(),
+            strings(&self.program_arguments),
+        ));
+        if let Some(ref path) = self.working_directory {
+            dict.push(("WorkingDirectory".to_string(), string(path)));
+        }
+        dict.push(("RunAtLoad".to_string(), Value::Boolean(self.run_at_load)));
+        if !self.limit_load_to_session_type.is_empty() {
+            dict.push((
+                "LimitLoadToSessionType".to_string(),
+                strings(&self.limit_load_to_session_type),
+            ));
+        }
+        if !self.environment_variables.is_empty() {
+            let variables = self
+                .environment_variables
+                .iter()
+                .map(|(name, value)| (name.to_string(), string(value)))
+                .collect();
+            dict.push((
+                "EnvironmentVariables".to_string(),
+                Value::Dictionary(variables),
+            ));
+        }
+        if let Some(ref path) = self.standard_out_path {
+            dict.push(("StandardOutPath".to_string(), string(path)));
+        }
+        if let Some(ref path) = self.standard_error_path {
+            dict.push(("StandardErrorPath".to_string(), string(path)));
+        }
+        if let Some(ref user_name) = self.user_name {
+            dict.push(("UserName".to_string(), string(user_name)));
+        }
+        if let Some(ref group_name) = self.group_name {
+            dict.push(("GroupName".to_string(), string(group_name)));
+        }
+        if let Some(interval) = self.start_interval {
+            dict.push(("StartInterval".to_string(), Value::Integer(interval.into())));
+        }
+        match self.start_calendar_interval.as_slice() {
+            [] => {}
+            [event] => dict.push(("StartCalendarInterval".to_string(), calendar_value(event))),
+            events => dict.push((
+                "StartCalendarInterval".to_string(),
+                Value::Array(events.iter().map(calendar_value).collect()),
+            )),
+        }
+        if !self.sockets.is_empty() {
+            let sockets = self
+                .sockets
+                .iter()
+                .map(|(name, socket)| (name.to_string(), socket.to_value()))
+                .collect();
+            dict.push(("Sockets".to_string(), Value::Dictionary(sockets)));
+        }
+        match self.keep_alive {
+            Some(KeepAlive::Always) => {
+                dict.push(("KeepAlive".to_string(), Value::Boolean(true)));
+            }
+            Some(KeepAlive::Conditions(ref conditions)) => {
+                dict.push(("KeepAlive".to_string(), conditions.to_value()));
+            }
+            None => {}
+        }
+        if let Some(interval) = self.throttle_interval {
+            dict.push((
+                "ThrottleInterval".to_string(),
+                Value::Integer(interval.into()),
+            ));
+        }
+
+        Value::Dictionary(dict)
+    }
+
+    /// Sets `KeepAlive` and `ThrottleInterval` from `policy`. launchd has no burst limit, so it is ignored.
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    /// use ceviche::launchd::{KeepAlive, KeepAliveConditions, LaunchdPlist};
+    /// use ceviche::restart::RestartPolicy;
+    ///
+    /// let mut policy = RestartPolicy::on_failure();
+    /// policy.delay = Duration::from_secs(30);
+    ///
+    /// let mut plist = LaunchdPlist::new("com.example.foobar", vec!["/usr/local/bin/foobar".to_string()]);
+    /// plist.set_restart_policy(&policy);
+    ///
+    /// let conditions = KeepAliveConditions { successful_exit: Some(false), ..Default::default() };
+    /// assert_eq!(plist.keep_alive, Some(KeepAlive::Conditions(conditions)));
+    /// assert_eq!(plist.throttle_interval, Some(30));
+    /// assert!(plist.render().contains("<key>KeepAlive</key>\n\t<dict>\n\t\t<key>SuccessfulExit</key>\n\t\t<false/>"));
+    /// ```
+    pub fn set_restart_policy(&mut self, policy: &RestartPolicy) {
+        self.keep_alive = match policy.restart {
+            Restart::Never => None,
+            Restart::OnFailure => Some(KeepAlive::Conditions(KeepAliveConditions {
+                successful_exit: Some(false),
+                ..KeepAliveConditions::default()
+            })),
+            Restart::Always => Some(KeepAlive::Always),
+        };
+        self.throttle_interval = match policy.restart {
+            Restart::Never => None,
+            _ => Some(policy.delay.as_secs() as u32),
+        };
+    }
+
+    /// Keeps the job alive only while the jobs it requires are loaded (`KeepAlive` `OtherJobEnabled`),
+    /// launchd has no other kind of dependency. Conditions of the restart policy are kept, except
+    /// `KeepAlive` `true` which would keep the job alive regardless of its dependencies.
+    ///
+    /// Dependencies are skipped for jobs that are not kept alive, with `RestartPolicy::never()`:
+    /// `OtherJobEnabled` would make launchd start them whenever the jobs they require are loaded.
+    ///
+    /// ```rust
+    /// use ceviche::dependency::Dependency;
+    /// use ceviche::launchd::{KeepAlive, KeepAliveConditions, LaunchdPlist};
+    /// use ceviche::restart::RestartPolicy;
+    ///
+    /// let mut plist = LaunchdPlist::new("com.foobar", vec!["/usr/local/bin/foobar".to_string()]);
+    /// plist.set_restart_policy(&RestartPolicy::always());
+    /// plist.add_dependencies(&[Dependency::requires("org.postgresql.postgres"), Dependency::after("com.syslog")]);
+    ///
+    /// let conditions = KeepAliveConditions {
+    ///     other_job_enabled: vec![("org.postgresql.postgres".to_string(), true)],
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(plist.keep_alive, Some(KeepAlive::Conditions(conditions)));
+    ///
+    /// plist.set_restart_policy(&RestartPolicy::never());
+    /// plist.add_dependencies(&[Dependency::requires("org.postgresql.postgres")]);
+    /// assert_eq!(plist.keep_alive, None);
+    /// ```
+    pub fn add_dependencies(&mut self, dependencies: &[Dependency]) {
+        let required: Vec<(String, bool)> = dependencies
+            .iter()
+            .filter(|dependency| dependency.is_required())
+            .map(|dependency| (dependency.name.to_string(), true))
+            .collect();
+        if required.is_empty() {
+            return;
+        }
+
+        let mut conditions = match self.keep_alive.take() {
+            Some(KeepAlive::Conditions(conditions)) => conditions,
+            Some(KeepAlive::Always) => KeepAliveConditions::default(),
+            None => return,
+        };
+        conditions.other_job_enabled.extend(required);
+        self.keep_alive = Some(KeepAlive::Conditions(conditions));
+    }
+
+    /// Renders the job definition as a property list XML document.
+    pub fn render(&self) -> String {
+        self.to_value().to_xml()
+    }
+}
+
+impl KeepAliveConditions {
+    fn to_value(&self) -> Value {
+        let mut dict = Vec::new();
+        let states = |states: &[(String, bool)]| {
+            Value::Dictionary(
+                states
+                    .iter()
+                    .map(|(name, state)| (name.to_string(), Value::Boolean(*state)))
+                    .collect(),
+            )
+        };
+
+        if let Some(successful_exit) = self.successful_exit {
+            dict.push((
+                "SuccessfulExit".to_string(),
+                Value::Boolean(successful_exit),
+            ));
+        }
+        if let Some(crashed) = self.crashed {
+            dict.push(("Crashed".to_string(), Value::Boolean(crashed)));
+        }
+        if let Some(network_state) = self.network_state {
+            dict.push(("NetworkState".to_string(), Value::Boolean(network_state)));
+        }
+        if !self.path_state.is_empty() {
+            dict.push(("PathState".to_string(), states(&self.path_state)));
+        }
+        if !self.other_job_enabled.is_empty() {
+            dict.push((
+                "OtherJobEnabled".to_string(),
+                states(&self.other_job_enabled),
+            ));
+        }
+
+        Value::Dictionary(dict)
+    }
+}
+
+fn calendar_value(event: &CalendarInterval) -> Value {
+    let entries = [
+        ("Month", event.month),
+        ("Day", event.day),
+        ("Weekday", event.weekday),
+        ("Hour", event.hour),
+        ("Minute", event.minute),
+    ];
+
+    Value::Dictionary(
+        entries
+            .iter()
+            .filter_map(|(key, value)| {
+                value.map(|value| (key.to_string(), Value::Integer(value.into())))
+            })
+            .collect(),
+    )
+}
+
+impl From<&ListenSocket> for LaunchdSocket {
+    fn from(socket: &ListenSocket) -> LaunchdSocket {
+        let (sock_type, address) = match socket {
+            ListenSocket::Stream(address) => ("stream", address),
+            ListenSocket::Datagram(address) => ("dgram", address),
+        };
+
+        let mut launchd_socket = LaunchdSocket {
+            sock_type: Some(sock_type.to_string()),
+            ..LaunchdSocket::default()
+        };
+        if address.starts_with('/') {
+            launchd_socket.path_name = Some(address.to_string());
+            launchd_socket.family = Some("Unix".to_string());
+        } else {
+            match address.rsplit_once(':') {
+                Some((node, service)) => {
+                    let node = node.trim_start_matches('[').trim_end_matches(']');
+                    launchd_socket.node_name = Some(node.to_string());
+                    launchd_socket.service_name = Some(service.to_string());
+                }
+                None => launchd_socket.service_name = Some(address.to_string()),
+            }
+        }
+        launchd_socket
+    }
+}
+
+impl LaunchdSocket {
+    fn to_value(&self) -> Value {
+        let entries = [
+            ("SockNodeName", &self.node_name),
+            ("SockServiceName", &self.service_name),
+            ("SockPathName", &self.path_name),
+            ("SockType", &self.sock_type),
+            ("SockFamily", &self.family),
+        ];
+
+        Value::Dictionary(
+            entries
+                .iter()
+                .filter_map(|(key, value)| {
+                    value
+                        .as_ref()
+                        .map(|value| (key.to_string(), Value::String(value.to_string())))
+                })
+                .collect(),
+        )
+    }
+}
+
+pub(crate) fn xml_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn xml_unescape(value: &str) -> Result<String, Error> {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find(';')
+            .ok_or("Unterminated entity in plist")?;
+        let entity = &rest[start + 1..start + end];
+        let c = match entity {
+            "amp" => '&',
+            "lt" => '<',
+            "gt" => '>',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => {
+                let code = match entity.strip_prefix("#x") {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => entity.strip_prefix('#').and_then(|dec| dec.parse().ok()),
+                };
+                code.and_then(char::from_u32)
+                    .ok_or_else(|| Error::new(&format!("Invalid entity &{}; in plist", entity)))?
+            }
+        };
+        unescaped.push(c);
+        rest = &rest[start + end + 1..];
+    }
+    unescaped.push_str(rest);
+    Ok(unescaped)
+}
+
+struct Parser<'a> {
+    input: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        self.input = self.input.trim_start();
+    }
+
+    fn skip_prolog(&mut self) {
+        loop {
+            self.skip_whitespace();
+            if self.input.starts_with("<?") || self.input.starts_with("<!") {
+                match self.input.find('>') {
+                    Some(end) => self.input = &self.input[end + 1..],
+                    None => return,
+                }
+            } else {
+                return;
+            }
+        }
+    }
+
+    /// Reads the next tag, returning its name and whether it is self-closing.
+    fn next_tag(&mut self) -> Result<(&'a str, bool), Error> {
+        self.skip_whitespace();
+        if !self.input.starts_with('<') {
+            return Err(Error::new("Expected a tag in plist"));
+        }
+        let end = self.input.find('>').ok_or("Unterminated tag in plist")?;
+        let tag = &self.input[1..end];
+        self.input = &self.input[end + 1..];
+
+        let (tag, empty) = match tag.strip_suffix('/') {
+            Some(tag) => (tag, true),
+            None => (tag, false),
+        };
+        let name = tag.split_whitespace().next().unwrap_or_default();
+        Ok((name, empty))
+    }
+
+    fn expect_open(&mut self, name: &str) -> Result<(), Error> {
+        match self.next_tag()? {
+            (tag, false) if tag == name => Ok(()),
+            (tag, _) => Err(Error::new(&format!(
+                "Expected <{}> in plist, found <{}>",
+                name, tag
+            ))),
+        }
+    }
+
+    fn expect_close(&mut self, name: &str) -> Result<(), Error> {
+        match self.next_tag()? {
+            (tag, false) if tag.strip_prefix('/') == Some(name) => Ok(()),
+            (tag, _) => Err(Error::new(&format!(
+                "Expected </{}> in plist, found <{}>",
+                name, tag
+            ))),
+        }
+    }
+
+    fn is_close(&mut self) -> bool {
+        self.skip_whitespace();
+        self.input.starts_with("</")
+    }
+
+    fn text(&mut self, name: &str) -> Result<String, Error> {
+        let end = self
+            .input
+            .find('<')
+            .ok_or_else(|| Error::new(&format!("Unterminated <{}> in plist", name)))?;
+        let text = xml_unescape(&self.input[..end])?;
+        self.input = &self.input[end..];
+        self.expect_close(name)?;
+        Ok(text)
+    }
+
+    fn parse_value(&mut self) -> Result<Value, Error> {
+        let (tag, empty) = self.next_tag()?;
+        match (tag, empty) {
+            ("true", true) => Ok(Value::Boolean(true)),
+            ("false", true) => Ok(Value::Boolean(false)),
+            ("string", true) => Ok(Value::String(String::new())),
+            ("array", true) => Ok(Value::Array(Vec::new())),
+            ("dict", true) => Ok(Value::Dictionary(Vec::new())),
+            ("string", false) => Ok(Value::String(self.text("string")?)),
+            ("integer", false) => {
+                let text = self.text("integer")?;
+                let value = text
+                    .trim()
+                    .parse()
+                    .map_err(|_| Error::new(&format!("Invalid integer {} in plist", text)))?;
+                Ok(Value::Integer(value))
+            }
+            ("array", false) => {
+                let mut values = Vec::new();
+                while !self.is_close() {
+                    values.push(self.parse_value()?);
+                }
+                self.expect_close("array")?;
+                Ok(Value::Array(values))
+            }
+            ("dict", false) => {
+                let mut entries = Vec::new();
+                while !self.is_close() {
+                    self.expect_open("key")?;
+                    let key = self.text("key")?;
+                    entries.push((key, self.parse_value()?));
+                }
+                self.expect_close("dict")?;
+                Ok(Value::Dictionary(entries))
+            }
+            (tag, _) => Err(Error::new(&format!("Unsupported <{}> in plist", tag))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn restart_plist(restart: Restart, burst: Option<u32>) -> LaunchdPlist {
+        let mut policy = RestartPolicy::new(restart);
+        policy.delay = Duration::from_secs(10);
+        policy.burst = burst;
+
+        let mut plist = LaunchdPlist::new("com.foobar", vec!["/usr/local/bin/foobar".to_string()]);
+        plist.set_restart_policy(&policy);
+        plist
+    }
+
+    #[test]
+    fn restart_never() {
+        for burst in [None, Some(3)] {
+            let plist = restart_plist(Restart::Never, burst);
+            assert_eq!(plist.keep_alive, None);
+            assert_eq!(plist.throttle_interval, None);
+            assert!(!plist.render().contains("<key>KeepAlive</key>"));
+        }
+    }
+
+    #[test]
+    fn restart_on_failure() {
+        let conditions = KeepAliveConditions {
+            successful_exit: Some(false),
+            ..KeepAliveConditions::default()
+        };
+        // launchd has no burst limit, the policy is the same with and without one.
+        for burst in [None, Some(3)] {
+            let plist = restart_plist(Restart::OnFailure, burst);
+            assert_eq!(
+                plist.keep_alive,
+                Some(KeepAlive::Conditions(conditions.clone()))
+            );
+            assert_eq!(plist.throttle_interval, Some(10));
+            assert!(plist
+                .render()
+                .contains("<key>ThrottleInterval</key>\n\t<integer>10</integer>"));
+        }
+    }
+
+    #[test]
+    fn restart_always() {
+        for burst in [None, Some(3)] {
+            let plist = restart_plist(Restart::Always, burst);
+            assert_eq!(plist.keep_alive, Some(KeepAlive::Always));
+            assert_eq!(plist.throttle_interval, Some(10));
+            assert!(plist.render().contains("<key>KeepAlive</key>\n\t<true/>"));
+        }
+    }
+}
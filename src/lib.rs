@@ -71,9 +71,31 @@
 #[macro_use]
 extern crate cfg_if;
 
+#[cfg(all(feature = "control", any(target_os = "linux", target_os = "macos")))]
+pub mod control;
 /// Manages the service on the system.
 pub mod controller;
+pub mod definition;
+pub mod dependency;
+#[cfg(feature = "health")]
+pub mod health;
+#[cfg(any(feature = "health", feature = "metrics"))]
+mod http;
+#[cfg(target_os = "linux")]
+pub mod instance;
+pub mod launchd;
+pub mod logging;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub mod pidfile;
+pub mod restart;
+pub mod schedule;
+pub mod scm;
 pub mod session;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod socket;
+pub mod systemd;
 
 #[cfg(windows)]
 pub use winapi;
@@ -0,0 +1,326 @@
+//! `log` implementations writing to the native log sink of the service manager.
+//!
+//! Loggers are installed with `init()`. Services running in standalone mode log to the console.
+//!
+//! `dispatch` sets up logging before calling the service main function, with `init_default()` unless
+//! another setup function is configured with `set_setup()`. A logger installed before `register()` is
+//! kept, but a service main function installing its own logger must disable the setup, as the `log`
+//! crate only accepts one logger per process:
+//!
+//! ```rust,ignore
+//! fn main() {
+//!     // Keep the logging configured by the service main function.
+//!     ceviche::logging::set_setup(None);
+//!
+//!     let mut controller = Controller::new(SERVICE_NAME, DISPLAY_NAME, DESCRIPTION);
+//!     let _result = controller.register(service_main_wrapper);
+//! }
+//! ```
+
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use crate::Error;
+
+mod rotate;
+#[cfg(unix)]
+pub use self::rotate::reopen_on_sighup;
+pub use self::rotate::{logrotate_snippet, RotatingFile, RotationPolicy};
+
+cfg_if! {
+    if #[cfg(windows)] {
+        mod eventlog;
+        pub use self::eventlog::EventLogger;
+    } else if #[cfg(target_os = "linux")] {
+        mod journald;
+        mod syslog;
+        pub use self::journald::JournaldLogger;
+        pub use self::syslog::{Facility, SyslogFormat, SyslogLogger, SyslogTarget};
+    } else if #[cfg(target_os = "macos")] {
+        mod syslog;
+        pub use self::syslog::{Facility, SyslogFormat, SyslogLogger, SyslogTarget};
+    }
+}
+
+/// Converts a UNIX timestamp to its UTC date and time: year, month, day, hours, minutes and seconds.
+pub(crate) fn utc_datetime(timestamp: u64) -> (u64, u64, u64, u64, u64, u64) {
+    let (days, seconds) = (timestamp / 86400, timestamp % 86400);
+
+    // Civil from days, shifting the year to start in March.
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days % 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+    )
+}
+
+fn timestamp() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let (year, month, day, hours, minutes, seconds) = utc_datetime(now.as_secs());
+    format!(
+        "{}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, hours, minutes, seconds
+    )
+}
+
+/// Logs to the standard output.
+pub struct ConsoleLogger {
+    pub level: LevelFilter,
+    /// Colors the level of each message.
+    pub colored: bool,
+}
+
+impl ConsoleLogger {
+    pub fn new(level: LevelFilter) -> ConsoleLogger {
+        ConsoleLogger {
+            level,
+            colored: false,
+        }
+    }
+}
+
+impl Log for ConsoleLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        if self.colored {
+            let color = match record.level() {
+                Level::Error => 31,
+                Level::Warn => 33,
+                Level::Info => 32,
+                Level::Debug => 34,
+                Level::Trace => 35,
+            };
+            let _ = writeln!(
+                io::stdout().lock(),
+                "\x1b[{}m{:<5}\x1b[0m {}: {}",
+                color,
+                record.level(),
+                record.target(),
+                record.args()
+            );
+        } else {
+            let _ = writeln!(
+                io::stdout().lock(),
+                "{:<5} {}: {}",
+                record.level(),
+                record.target(),
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Appends timestamped messages to a file.
+pub struct FileLogger {
+    pub level: LevelFilter,
+    file: Mutex<RotatingFile>,
+}
+
+impl FileLogger {
+    pub fn new(path: &Path, level: LevelFilter) -> Result<FileLogger, Error> {
+        FileLogger::with_rotation(path, RotationPolicy::new(), level)
+    }
+
+    /// Logs to `path`, rotated according to `policy`.
+    pub fn with_rotation(
+        path: &Path,
+        policy: RotationPolicy,
+        level: LevelFilter,
+    ) -> Result<FileLogger, Error> {
+        Ok(FileLogger {
+            level,
+            file: Mutex::new(RotatingFile::new(path, policy)?),
+        })
+    }
+}
+
+impl Log for FileLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        if let Ok(mut file) = self.file.lock() {
+            let _ = writeln!(
+                file,
+                "{} {:<5} {}: {}",
+                timestamp(),
+                record.level(),
+                record.target(),
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut file) = self.file.lock() {
+            let _ = file.flush();
+        }
+    }
+}
+
+/// Installs `logger` as the logger of the process, with `level` as the maximum level. Does nothing when
+/// a logger is already installed, such as one set up by the service before calling `register()`.
+pub fn init(logger: Box<dyn Log>, level: LevelFilter) -> Result<(), Error> {
+    if log::set_boxed_logger(logger).is_ok() {
+        log::set_max_level(level);
+    }
+    Ok(())
+}
+
+/// Logs to the journal under `service_name`, or to the console in standalone mode and when the journal
+/// is not available.
+#[cfg(target_os = "linux")]
+pub fn init_journald(
+    service_name: &str,
+    standalone_mode: bool,
+    level: LevelFilter,
+) -> Result<(), Error> {
+    if standalone_mode || !JournaldLogger::is_available() {
+        init(Box::new(ConsoleLogger::new(level)), level)
+    } else {
+        init(Box::new(JournaldLogger::new(service_name, level)?), level)
+    }
+}
+
+/// Logs to syslog under `service_name`, or to the console in standalone mode.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn init_syslog(
+    service_name: &str,
+    standalone_mode: bool,
+    level: LevelFilter,
+) -> Result<(), Error> {
+    if standalone_mode {
+        init(Box::new(ConsoleLogger::new(level)), level)
+    } else {
+        init(Box::new(SyslogLogger::new(service_name, level)), level)
+    }
+}
+
+/// Name of the environment variable holding the log level of `service_name`, such as `FOOBAR_LOG`.
+///
+/// ```rust
+/// use ceviche::logging::level_env_var;
+///
+/// assert_eq!(level_env_var("foobar"), "FOOBAR_LOG");
+/// assert_eq!(level_env_var("foo-bar.service"), "FOO_BAR_SERVICE_LOG");
+/// ```
+pub fn level_env_var(service_name: &str) -> String {
+    let name: String = service_name
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c.to_ascii_uppercase(),
+            false => '_',
+        })
+        .collect();
+    format!("{}_LOG", name)
+}
+
+/// Log level of `service_name`, read from its environment variable and `Info` by default.
+pub fn level_from_env(service_name: &str) -> LevelFilter {
+    env::var(level_env_var(service_name))
+        .ok()
+        .and_then(|level| level.trim().parse().ok())
+        .unwrap_or(LevelFilter::Info)
+}
+
+fn native_logger(service_name: &str, level: LevelFilter) -> Result<Box<dyn Log>, Error> {
+    cfg_if! {
+        if #[cfg(windows)] {
+            Ok(Box::new(EventLogger::new(service_name, level)?))
+        } else if #[cfg(target_os = "linux")] {
+            if JournaldLogger::is_available() {
+                Ok(Box::new(JournaldLogger::new(service_name, level)?))
+            } else if Path::new("/dev/log").exists() {
+                Ok(Box::new(SyslogLogger::new(service_name, level)))
+            } else {
+                Ok(Box::new(ConsoleLogger::new(level)))
+            }
+        } else if #[cfg(target_os = "macos")] {
+            // Agents run as the logged in user, who can only write to their own logs directory.
+            let dir = match (unsafe { libc::geteuid() }, env::var_os("HOME")) {
+                (0, _) | (_, None) => Path::new("/Library/Logs").to_path_buf(),
+                (_, Some(home)) => Path::new(&home).join("Library/Logs"),
+            };
+            let path = dir.join(format!("{}.log", service_name));
+            Ok(Box::new(FileLogger::new(&path, level)?))
+        } else {
+            Ok(Box::new(ConsoleLogger::new(level)))
+        }
+    }
+}
+
+/// Default logging setup: a colored console in standalone mode, and the native log sink of the platform
+/// in service mode: the journal (or syslog without systemd) on Linux, `/Library/Logs/<service_name>.log`
+/// on macOS (`~/Library/Logs/<service_name>.log` for agents) and the event log on Windows, whose source is
+/// registered when the service is created. The level is read from the `<SERVICE>_LOG` environment variable.
+pub fn init_default(service_name: &str, standalone_mode: bool) -> Result<(), Error> {
+    let level = level_from_env(service_name);
+    if standalone_mode {
+        let mut logger = ConsoleLogger::new(level);
+        logger.colored = io::stdout().is_terminal();
+        init(Box::new(logger), level)
+    } else {
+        init(native_logger(service_name, level)?, level)
+    }
+}
+
+/// Signature of the function setting up logging, called with the service name and the standalone mode.
+pub type SetupFn = fn(service_name: &str, standalone_mode: bool) -> Result<(), Error>;
+
+static SETUP: Mutex<Option<SetupFn>> = Mutex::new(Some(init_default));
+
+/// Replaces the function `dispatch` sets up logging with, `None` disables the setup.
+pub fn set_setup(setup: Option<SetupFn>) {
+    if let Ok(mut current) = SETUP.lock() {
+        *current = setup;
+    }
+}
+
+/// Sets up logging with the configured setup function, for `dispatch` or a service running in
+/// standalone mode.
+pub fn setup(service_name: &str, standalone_mode: bool) -> Result<(), Error> {
+    let setup = SETUP.lock().ok().and_then(|setup| *setup);
+    match setup {
+        Some(setup) => setup(service_name, standalone_mode),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,87 @@
+use std::ffi::OsStr;
+use std::iter::once;
+use std::os::windows::ffi::OsStrExt;
+use std::ptr;
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use winapi::shared::minwindef::WORD;
+use winapi::um::winbase::{DeregisterEventSource, RegisterEventSourceW, ReportEventW};
+use winapi::um::winnt::{
+    EVENTLOG_ERROR_TYPE, EVENTLOG_INFORMATION_TYPE, EVENTLOG_WARNING_TYPE, HANDLE, LPCWSTR,
+};
+
+use crate::Error;
+
+fn to_wide(value: &str) -> Vec<u16> {
+    OsStr::new(value).encode_wide().chain(once(0)).collect()
+}
+
+fn event_type(level: Level) -> WORD {
+    match level {
+        Level::Error => EVENTLOG_ERROR_TYPE,
+        Level::Warn => EVENTLOG_WARNING_TYPE,
+        Level::Info | Level::Debug | Level::Trace => EVENTLOG_INFORMATION_TYPE,
+    }
+}
+
+/// Logs to the Windows event log, with the service name as event source.
+pub struct EventLogger {
+    pub level: LevelFilter,
+    handle: HANDLE,
+}
+
+// The event log handle can be used from any thread.
+unsafe impl Send for EventLogger {}
+unsafe impl Sync for EventLogger {}
+
+impl EventLogger {
+    pub fn new(service_name: &str, level: LevelFilter) -> Result<EventLogger, Error> {
+        let source = to_wide(service_name);
+        let handle = unsafe { RegisterEventSourceW(ptr::null(), source.as_ptr()) };
+        if handle.is_null() {
+            return Err(Error::new(&format!(
+                "Failed to register event source {}: {}",
+                service_name,
+                std::io::Error::last_os_error()
+            )));
+        }
+
+        Ok(EventLogger { level, handle })
+    }
+}
+
+impl Drop for EventLogger {
+    fn drop(&mut self) {
+        unsafe { DeregisterEventSource(self.handle) };
+    }
+}
+
+impl Log for EventLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let message = to_wide(&format!("{}: {}", record.target(), record.args()));
+        let mut strings: [LPCWSTR; 1] = [message.as_ptr()];
+        unsafe {
+            ReportEventW(
+                self.handle,
+                event_type(record.level()),
+                0,
+                0,
+                ptr::null_mut(),
+                1,
+                0,
+                strings.as_mut_ptr(),
+                ptr::null_mut(),
+            );
+        }
+    }
+
+    fn flush(&self) {}
+}
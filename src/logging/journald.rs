@@ -0,0 +1,119 @@
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use crate::Error;
+
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+
+fn priority(level: Level) -> &'static str {
+    match level {
+        Level::Error => "3",
+        Level::Warn => "4",
+        Level::Info => "6",
+        Level::Debug | Level::Trace => "7",
+    }
+}
+
+/// Appends a field in the journal native protocol: values holding a newline are prefixed by their
+/// length instead of ending at the newline.
+fn add_field(datagram: &mut Vec<u8>, name: &str, value: &str) {
+    datagram.extend_from_slice(name.as_bytes());
+    if value.contains('\n') {
+        datagram.push(b'\n');
+        datagram.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        datagram.push(b'=');
+    }
+    datagram.extend_from_slice(value.as_bytes());
+    datagram.push(b'\n');
+}
+
+/// Logs to the systemd journal with native fields: `MESSAGE`, `PRIORITY`, `SYSLOG_IDENTIFIER`,
+/// `CODE_FILE` and `CODE_LINE`.
+///
+/// ```rust
+/// use std::os::unix::net::UnixDatagram;
+/// use log::{Level, LevelFilter, Log, Record};
+/// use ceviche::logging::JournaldLogger;
+///
+/// let path = std::env::temp_dir().join(format!("ceviche-doctest-{}.journal", std::process::id()));
+/// let journal = UnixDatagram::bind(&path).unwrap();
+///
+/// let logger = JournaldLogger::with_socket("foobar", LevelFilter::Info, &path).unwrap();
+/// logger.log(&Record::builder()
+///     .args(format_args!("disk full\non /var"))
+///     .level(Level::Warn)
+///     .file(Some("src/main.rs"))
+///     .line(Some(42))
+///     .build());
+///
+/// let mut datagram = [0; 1024];
+/// let size = journal.recv(&mut datagram).unwrap();
+/// assert_eq!(&datagram[..size], &b"MESSAGE\n\x11\0\0\0\0\0\0\0disk full\non /var\n\
+///     PRIORITY=4\nSYSLOG_IDENTIFIER=foobar\nCODE_FILE=src/main.rs\nCODE_LINE=42\n"[..]);
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+pub struct JournaldLogger {
+    pub identifier: String,
+    pub level: LevelFilter,
+    socket: UnixDatagram,
+    path: PathBuf,
+}
+
+impl JournaldLogger {
+    /// Logs to the journal with `service_name` as `SYSLOG_IDENTIFIER`.
+    pub fn new(service_name: &str, level: LevelFilter) -> Result<JournaldLogger, Error> {
+        JournaldLogger::with_socket(service_name, level, Path::new(JOURNALD_SOCKET))
+    }
+
+    /// Logs to the journal listening on `path`.
+    pub fn with_socket(
+        service_name: &str,
+        level: LevelFilter,
+        path: &Path,
+    ) -> Result<JournaldLogger, Error> {
+        let socket = UnixDatagram::unbound()
+            .map_err(|e| Error::new(&format!("Failed to create journal socket: {}", e)))?;
+
+        Ok(JournaldLogger {
+            identifier: service_name.to_string(),
+            level,
+            socket,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Returns whether the journal socket exists.
+    pub fn is_available() -> bool {
+        Path::new(JOURNALD_SOCKET).exists()
+    }
+}
+
+impl Log for JournaldLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let mut datagram = Vec::new();
+        add_field(&mut datagram, "MESSAGE", &record.args().to_string());
+        add_field(&mut datagram, "PRIORITY", priority(record.level()));
+        add_field(&mut datagram, "SYSLOG_IDENTIFIER", &self.identifier);
+        if let Some(file) = record.file() {
+            add_field(&mut datagram, "CODE_FILE", file);
+        }
+        if let Some(line) = record.line() {
+            add_field(&mut datagram, "CODE_LINE", &line.to_string());
+        }
+
+        let _ = self.socket.send_to(&datagram, &self.path);
+    }
+
+    fn flush(&self) {}
+}
@@ -0,0 +1,306 @@
+use std::fmt::Write as _;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+use crate::Error;
+
+static SIGHUP_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(unix)]
+extern "C" fn on_sighup(_signal: libc::c_int) {
+    SIGHUP_COUNT.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Makes every `RotatingFile` reopen its file when the process receives `SIGHUP`, after an external tool
+/// such as logrotate moved it.
+///
+/// This replaces the default handling of `SIGHUP`, which `dispatch` treats as a stop request otherwise.
+/// `dispatch` calls it for services with a log file.
+#[cfg(unix)]
+pub fn reopen_on_sighup() -> Result<(), Error> {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = on_sighup as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(libc::SIGHUP, &action, std::ptr::null_mut()) != 0 {
+            return Err(Error::new(&format!(
+                "Failed to set SIGHUP handler: {}",
+                io::Error::last_os_error()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// When a log file is rotated, and how many rotated files are kept.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RotationPolicy {
+    /// Rotates the file before it grows past this size, in bytes.
+    pub max_size: Option<u64>,
+    /// Rotates the file once it is older than this.
+    pub interval: Option<Duration>,
+    /// Number of rotated files kept, as `<path>.1` (the most recent) to `<path>.<keep>`.
+    pub keep: usize,
+    /// Compresses rotated files with `gzip`, in the background.
+    pub compress: bool,
+}
+
+impl RotationPolicy {
+    /// Never rotates the file.
+    pub fn new() -> RotationPolicy {
+        RotationPolicy {
+            max_size: None,
+            interval: None,
+            keep: 5,
+            compress: false,
+        }
+    }
+
+    pub fn size(max_size: u64) -> RotationPolicy {
+        RotationPolicy {
+            max_size: Some(max_size),
+            ..RotationPolicy::new()
+        }
+    }
+
+    pub fn interval(interval: Duration) -> RotationPolicy {
+        RotationPolicy {
+            interval: Some(interval),
+            ..RotationPolicy::new()
+        }
+    }
+}
+
+impl Default for RotationPolicy {
+    fn default() -> RotationPolicy {
+        RotationPolicy::new()
+    }
+}
+
+/// A file appended to, rotated according to a `RotationPolicy`.
+///
+/// ```rust
+/// use std::io::Write;
+/// use ceviche::logging::{RotatingFile, RotationPolicy};
+///
+/// let dir = std::env::temp_dir().join(format!("ceviche-doctest-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let path = dir.join("foobar.log");
+///
+/// let mut policy = RotationPolicy::size(16);
+/// policy.keep = 2;
+/// let mut file = RotatingFile::new(&path, policy).unwrap();
+/// for line in &["first line\n", "second line\n", "third line\n", "fourth line\n"] {
+///     file.write_all(line.as_bytes()).unwrap();
+/// }
+///
+/// assert_eq!(std::fs::read_to_string(&path).unwrap(), "fourth line\n");
+/// assert_eq!(std::fs::read_to_string(dir.join("foobar.log.1")).unwrap(), "third line\n");
+/// assert_eq!(std::fs::read_to_string(dir.join("foobar.log.2")).unwrap(), "second line\n");
+/// assert!(!dir.join("foobar.log.3").exists());
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub struct RotatingFile {
+    path: PathBuf,
+    policy: RotationPolicy,
+    file: File,
+    size: u64,
+    /// When the file was last rotated, by this process or by an external tool.
+    rotated: SystemTime,
+    sighup_count: usize,
+    compression: Option<JoinHandle<()>>,
+}
+
+fn open_append(path: &Path) -> io::Result<(File, u64)> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata()?.len();
+    Ok((file, size))
+}
+
+impl RotatingFile {
+    pub fn new(path: &Path, policy: RotationPolicy) -> Result<RotatingFile, Error> {
+        let (file, size) = open_append(path)
+            .map_err(|e| Error::new(&format!("Failed to open {}: {}", path.display(), e)))?;
+        // The file was created by the last rotation, which is the creation time of an existing file when
+        // the file system records it.
+        let rotated = match size {
+            0 => SystemTime::now(),
+            _ => file
+                .metadata()
+                .and_then(|metadata| metadata.created())
+                .unwrap_or_else(|_| SystemTime::now()),
+        };
+
+        Ok(RotatingFile {
+            path: path.to_path_buf(),
+            policy,
+            file,
+            size,
+            rotated,
+            sighup_count: SIGHUP_COUNT.load(Ordering::SeqCst),
+            compression: None,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn rotated_path(&self, index: usize, extension: &str) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}{}", index, extension));
+        PathBuf::from(path)
+    }
+
+    fn reopen(&mut self) -> io::Result<()> {
+        let (file, size) = open_append(&self.path)?;
+        self.file = file;
+        self.size = size;
+        Ok(())
+    }
+
+    /// Rotates the file now.
+    pub fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        // The previous rotated file is shifted below, its compression must be done first.
+        if let Some(compression) = self.compression.take() {
+            let _ = compression.join();
+        }
+
+        for extension in &["", ".gz"] {
+            let _ = fs::remove_file(self.rotated_path(self.policy.keep, extension));
+            for index in (1..self.policy.keep).rev() {
+                let _ = fs::rename(
+                    self.rotated_path(index, extension),
+                    self.rotated_path(index + 1, extension),
+                );
+            }
+        }
+
+        if self.policy.keep > 0 {
+            fs::rename(&self.path, self.rotated_path(1, ""))?;
+            if self.policy.compress {
+                let path = self.rotated_path(1, "");
+                self.compression = Some(thread::spawn(move || {
+                    let _ = Command::new("gzip").arg("-f").arg(path).status();
+                }));
+            }
+        } else {
+            fs::remove_file(&self.path)?;
+        }
+
+        self.rotated = SystemTime::now();
+        self.reopen()
+    }
+
+    fn should_rotate(&self, length: usize) -> bool {
+        // A single write larger than the maximum size still goes to an empty file.
+        let too_large = self
+            .policy
+            .max_size
+            .is_some_and(|max_size| self.size > 0 && self.size + length as u64 > max_size);
+        let too_old = self.policy.interval.is_some_and(|interval| {
+            self.rotated
+                .elapsed()
+                .is_ok_and(|elapsed| elapsed >= interval)
+        });
+        too_large || too_old
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let sighup_count = SIGHUP_COUNT.load(Ordering::SeqCst);
+        if sighup_count != self.sighup_count {
+            // The file was moved by an external tool, which starts a new rotation interval.
+            self.sighup_count = sighup_count;
+            self.rotated = SystemTime::now();
+            self.reopen()?;
+        }
+        if self.should_rotate(buf.len()) {
+            self.rotate()?;
+        }
+
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Generates a logrotate configuration rotating `path` with `policy`, running `postrotate` after each
+/// rotation so the service reopens its log file.
+///
+/// ```rust
+/// use std::path::Path;
+/// use std::time::Duration;
+/// use ceviche::logging::{logrotate_snippet, RotationPolicy};
+///
+/// let mut policy = RotationPolicy::interval(Duration::from_secs(86400));
+/// policy.max_size = Some(10 * 1024 * 1024);
+/// policy.compress = true;
+///
+/// assert_eq!(logrotate_snippet(Path::new("/var/log/foobar.log"), &policy, Some("kill -HUP $(cat /run/foobar.pid)")), "\
+/// /var/log/foobar.log {
+///     daily
+///     maxsize 10485760
+///     rotate 5
+///     compress
+///     delaycompress
+///     missingok
+///     notifempty
+///     postrotate
+///         kill -HUP $(cat /run/foobar.pid)
+///     endscript
+/// }
+/// ");
+/// ```
+pub fn logrotate_snippet(path: &Path, policy: &RotationPolicy, postrotate: Option<&str>) -> String {
+    let mut snippet = String::new();
+    let _ = writeln!(snippet, "{} {{", path.display());
+
+    let frequency = policy.interval.map(|interval| match interval.as_secs() {
+        0..=3600 => "hourly",
+        3601..=86400 => "daily",
+        86401..=604800 => "weekly",
+        _ => "monthly",
+    });
+    match (frequency, policy.max_size) {
+        (Some(frequency), Some(max_size)) => {
+            let _ = writeln!(snippet, "    {}\n    maxsize {}", frequency, max_size);
+        }
+        (Some(frequency), None) => {
+            let _ = writeln!(snippet, "    {}", frequency);
+        }
+        (None, Some(max_size)) => {
+            let _ = writeln!(snippet, "    size {}", max_size);
+        }
+        (None, None) => {
+            let _ = writeln!(snippet, "    weekly");
+        }
+    }
+    let _ = writeln!(snippet, "    rotate {}", policy.keep);
+    if policy.compress {
+        snippet.push_str("    compress\n    delaycompress\n");
+    }
+    snippet.push_str("    missingok\n    notifempty\n");
+    if let Some(command) = postrotate {
+        let _ = writeln!(
+            snippet,
+            "    postrotate\n        {}\n    endscript",
+            command
+        );
+    }
+    snippet.push_str("}\n");
+
+    snippet
+}
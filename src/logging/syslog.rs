@@ -0,0 +1,292 @@
+use std::fmt::Write as _;
+use std::io::Write;
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+use std::process;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use super::utc_datetime;
+
+/// Converts a UNIX timestamp to the local date and time: year, month, day, hours, minutes and seconds.
+fn local_datetime(timestamp: u64) -> (u64, u64, u64, u64, u64, u64) {
+    let time = timestamp as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return utc_datetime(timestamp);
+    }
+    (
+        (tm.tm_year + 1900) as u64,
+        (tm.tm_mon + 1) as u64,
+        tm.tm_mday as u64,
+        tm.tm_hour as u64,
+        tm.tm_min as u64,
+        tm.tm_sec as u64,
+    )
+}
+
+/// Syslog facilities, as defined by RFC 5424.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Facility {
+    Kern = 0,
+    User = 1,
+    Mail = 2,
+    Daemon = 3,
+    Auth = 4,
+    Syslog = 5,
+    Lpr = 6,
+    News = 7,
+    Uucp = 8,
+    Cron = 9,
+    AuthPriv = 10,
+    Ftp = 11,
+    Local0 = 16,
+    Local1 = 17,
+    Local2 = 18,
+    Local3 = 19,
+    Local4 = 20,
+    Local5 = 21,
+    Local6 = 22,
+    Local7 = 23,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SyslogFormat {
+    /// BSD syslog format, understood by every syslog daemon.
+    Rfc3164,
+    Rfc5424,
+}
+
+/// Where syslog messages are sent.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SyslogTarget {
+    /// Datagram socket of the local syslog daemon, such as `/dev/log`.
+    Unix(PathBuf),
+    /// Remote syslog server, as `host:port`.
+    Udp(String),
+    /// Remote syslog server, as `host:port`. Messages are framed with their length in RFC 5424 format
+    /// and terminated by a newline in RFC 3164 format.
+    Tcp(String),
+}
+
+impl Default for SyslogTarget {
+    fn default() -> SyslogTarget {
+        if cfg!(target_os = "macos") {
+            SyslogTarget::Unix(PathBuf::from("/var/run/syslog"))
+        } else {
+            SyslogTarget::Unix(PathBuf::from("/dev/log"))
+        }
+    }
+}
+
+enum Transport {
+    Unix(UnixDatagram),
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+}
+
+fn severity(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+fn hostname() -> String {
+    let mut buffer = [0u8; 256];
+    match unsafe { libc::gethostname(buffer.as_mut_ptr() as *mut libc::c_char, buffer.len()) } {
+        0 => {
+            let length = buffer.iter().position(|c| *c == 0).unwrap_or(buffer.len());
+            String::from_utf8_lossy(&buffer[..length]).into_owned()
+        }
+        _ => "-".to_string(),
+    }
+}
+
+/// Logs to syslog.
+///
+/// ```rust
+/// use std::os::unix::net::UnixDatagram;
+/// use log::{Level, LevelFilter, Log, Record};
+/// use ceviche::logging::{Facility, SyslogLogger, SyslogTarget};
+///
+/// let path = std::env::temp_dir().join(format!("ceviche-doctest-{}.syslog", std::process::id()));
+/// let syslog = UnixDatagram::bind(&path).unwrap();
+///
+/// let mut logger = SyslogLogger::new("foobar", LevelFilter::Info);
+/// logger.target = SyslogTarget::Unix(path.clone());
+/// logger.facility = Facility::Local0;
+/// logger.log(&Record::builder().args(format_args!("started")).level(Level::Info).build());
+///
+/// let mut datagram = [0; 1024];
+/// let size = syslog.recv(&mut datagram).unwrap();
+/// let message = String::from_utf8_lossy(&datagram[..size]);
+/// assert!(message.starts_with("<134>"));
+/// assert!(message.ends_with(&format!(" foobar[{}]: started", std::process::id())));
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+pub struct SyslogLogger {
+    /// Application name (`TAG` in RFC 3164), defaults to the service name.
+    pub app_name: String,
+    pub facility: Facility,
+    pub format: SyslogFormat,
+    pub target: SyslogTarget,
+    pub level: LevelFilter,
+    hostname: String,
+    transport: Mutex<Option<Transport>>,
+}
+
+impl SyslogLogger {
+    /// Logs to the local syslog daemon in RFC 3164 format, with the daemon facility.
+    pub fn new(service_name: &str, level: LevelFilter) -> SyslogLogger {
+        SyslogLogger {
+            app_name: service_name.to_string(),
+            facility: Facility::Daemon,
+            format: SyslogFormat::Rfc3164,
+            target: SyslogTarget::default(),
+            level,
+            hostname: hostname(),
+            transport: Mutex::new(None),
+        }
+    }
+
+    fn format_message(&self, record: &Record) -> String {
+        let priority = (self.facility as u8) * 8 + severity(record.level());
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut message = String::new();
+        match self.format {
+            SyslogFormat::Rfc3164 => {
+                // RFC 3164 timestamps are in local time, without a time zone.
+                let (_, month, day, hours, minutes, seconds) = local_datetime(now.as_secs());
+                const MONTHS: [&str; 12] = [
+                    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov",
+                    "Dec",
+                ];
+                let _ = write!(
+                    message,
+                    "<{}>{} {:>2} {:02}:{:02}:{:02} ",
+                    priority,
+                    MONTHS[month as usize - 1],
+                    day,
+                    hours,
+                    minutes,
+                    seconds
+                );
+                // The local syslog daemon adds the hostname itself.
+                if !matches!(self.target, SyslogTarget::Unix(_)) {
+                    let _ = write!(message, "{} ", self.hostname);
+                }
+                let _ = write!(
+                    message,
+                    "{}[{}]: {}",
+                    self.app_name,
+                    process::id(),
+                    record.args()
+                );
+            }
+            SyslogFormat::Rfc5424 => {
+                let (year, month, day, hours, minutes, seconds) = utc_datetime(now.as_secs());
+                let _ = write!(
+                    message,
+                    "<{}>1 {}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z {} {} {} - - {}",
+                    priority,
+                    year,
+                    month,
+                    day,
+                    hours,
+                    minutes,
+                    seconds,
+                    now.subsec_millis(),
+                    self.hostname,
+                    self.app_name,
+                    process::id(),
+                    record.args()
+                );
+            }
+        }
+        message
+    }
+
+    fn connect(&self) -> std::io::Result<Transport> {
+        match self.target {
+            SyslogTarget::Unix(_) => UnixDatagram::unbound().map(Transport::Unix),
+            SyslogTarget::Udp(ref address) => {
+                let address = address.to_socket_addrs()?.next().ok_or_else(|| {
+                    std::io::Error::other(format!("Failed to resolve {}", address))
+                })?;
+                let socket = match address.is_ipv6() {
+                    true => UdpSocket::bind("[::]:0")?,
+                    false => UdpSocket::bind("0.0.0.0:0")?,
+                };
+                socket.connect(address)?;
+                Ok(Transport::Udp(socket))
+            }
+            SyslogTarget::Tcp(ref address) => TcpStream::connect(address).map(Transport::Tcp),
+        }
+    }
+
+    fn send(&self, transport: &mut Transport, message: &str) -> std::io::Result<()> {
+        match (transport, &self.target) {
+            (Transport::Unix(socket), SyslogTarget::Unix(path)) => {
+                socket.send_to(message.as_bytes(), path).map(|_| ())
+            }
+            (Transport::Udp(socket), SyslogTarget::Udp(_)) => {
+                socket.send(message.as_bytes()).map(|_| ())
+            }
+            (Transport::Tcp(stream), _) => match self.format {
+                SyslogFormat::Rfc3164 => stream.write_all(format!("{}\n", message).as_bytes()),
+                SyslogFormat::Rfc5424 => {
+                    stream.write_all(format!("{} {}", message.len(), message).as_bytes())
+                }
+            },
+            _ => Err(std::io::Error::other("The syslog target changed")),
+        }
+    }
+}
+
+impl Log for SyslogLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let message = self.format_message(record);
+        let mut transport = match self.transport.lock() {
+            Ok(transport) => transport,
+            Err(_) => return,
+        };
+
+        // A broken connection is reopened once, to survive restarts of the syslog server.
+        for _ in 0..2 {
+            if transport.is_none() {
+                *transport = self.connect().ok();
+            }
+            match transport.as_mut() {
+                Some(connection) => match self.send(connection, &message) {
+                    Ok(_) => return,
+                    Err(_) => *transport = None,
+                },
+                None => return,
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut transport) = self.transport.lock() {
+            if let Some(Transport::Tcp(ref mut stream)) = *transport {
+                let _ = stream.flush();
+            }
+        }
+    }
+}
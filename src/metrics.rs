@@ -0,0 +1,403 @@
+//! Runtime metrics of the service, in Prometheus text format.
+//!
+//! `dispatch` records the following metrics in the global `registry()`:
+//!
+//! - `ceviche_start_time_seconds`: start time of the service, in seconds since the UNIX epoch.
+//! - `ceviche_restarts`: number of restarts of the service by systemd (`NRestarts`).
+//! - `ceviche_last_exit_code`: return code of the service main function in the previous run of the
+//!   service, kept in `last_exit_code_path()`. Missing after a run that did not return, such as a crash.
+//! - `ceviche_events_total{event="..."}`: events received by the service main function.
+//! - `ceviche_session_events_total`: session events received by the service main function.
+//! - `ceviche_paused`: 1 while the service is paused.
+//! - `ceviche_pause_transitions_total{state="paused|running"}`: transitions between paused and running.
+//!
+//! Services add their own metrics to the same registry, which is exposed by a `MetricsServer` or rendered
+//! with `Registry::render()`.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::debug;
+
+use crate::http::{Listener, Response};
+use crate::Error;
+use crate::ServiceEvent;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MetricType {
+    Counter,
+    Gauge,
+}
+
+struct Metric {
+    help: String,
+    metric_type: MetricType,
+    samples: BTreeMap<String, f64>,
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn label_set(labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(name, value)| format!("{}=\"{}\"", name, escape(value)))
+        .collect();
+    format!("{{{}}}", labels.join(","))
+}
+
+/// Counters and gauges, identified by their name and labels.
+///
+/// ```rust
+/// use ceviche::metrics::Registry;
+///
+/// let registry = Registry::new();
+/// registry.add_counter("foobar_requests_total", "Requests handled.", &[("method", "GET")], 1.0);
+/// registry.add_counter("foobar_requests_total", "Requests handled.", &[("method", "GET")], 2.0);
+/// registry.set_gauge("foobar_queue_length", "Pending requests.", &[], 7.0);
+///
+/// assert_eq!(registry.get("foobar_requests_total", &[("method", "GET")]), Some(3.0));
+/// assert_eq!(registry.render(), "\
+/// ## HELP foobar_queue_length Pending requests.
+/// ## TYPE foobar_queue_length gauge
+/// foobar_queue_length 7
+/// ## HELP foobar_requests_total Requests handled.
+/// ## TYPE foobar_requests_total counter
+/// foobar_requests_total{method=\"GET\"} 3
+/// ");
+/// ```
+pub struct Registry {
+    metrics: Mutex<BTreeMap<String, Metric>>,
+}
+
+impl Registry {
+    pub const fn new() -> Registry {
+        Registry {
+            metrics: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    fn update<F: FnOnce(&mut f64)>(
+        &self,
+        name: &str,
+        help: &str,
+        metric_type: MetricType,
+        labels: &[(&str, &str)],
+        update: F,
+    ) {
+        if let Ok(mut metrics) = self.metrics.lock() {
+            let metric = metrics.entry(name.to_string()).or_insert_with(|| Metric {
+                help: help.to_string(),
+                metric_type,
+                samples: BTreeMap::new(),
+            });
+            update(metric.samples.entry(label_set(labels)).or_insert(0.0));
+        }
+    }
+
+    /// Adds `value` to a counter.
+    pub fn add_counter(&self, name: &str, help: &str, labels: &[(&str, &str)], value: f64) {
+        self.update(name, help, MetricType::Counter, labels, |sample| {
+            *sample += value
+        });
+    }
+
+    pub fn set_gauge(&self, name: &str, help: &str, labels: &[(&str, &str)], value: f64) {
+        self.update(name, help, MetricType::Gauge, labels, |sample| {
+            *sample = value
+        });
+    }
+
+    pub fn get(&self, name: &str, labels: &[(&str, &str)]) -> Option<f64> {
+        let metrics = self.metrics.lock().ok()?;
+        metrics
+            .get(name)
+            .and_then(|metric| metric.samples.get(&label_set(labels)).copied())
+    }
+
+    /// Renders the metrics in Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        if let Ok(metrics) = self.metrics.lock() {
+            for (name, metric) in metrics.iter() {
+                let metric_type = match metric.metric_type {
+                    MetricType::Counter => "counter",
+                    MetricType::Gauge => "gauge",
+                };
+                let _ = writeln!(text, "# HELP {} {}", name, metric.help);
+                let _ = writeln!(text, "# TYPE {} {}", name, metric_type);
+                for (labels, value) in metric.samples.iter() {
+                    let _ = writeln!(text, "{}{} {}", name, labels, value);
+                }
+            }
+        }
+        text
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Registry {
+        Registry::new()
+    }
+}
+
+static REGISTRY: Registry = Registry::new();
+
+/// Registry of the runtime metrics, shared with the metrics of the service.
+pub fn registry() -> &'static Registry {
+    &REGISTRY
+}
+
+fn event_name<T>(event: &ServiceEvent<T>) -> &'static str {
+    match event {
+        ServiceEvent::Continue => "Continue",
+        ServiceEvent::Pause => "Pause",
+        ServiceEvent::Stop => "Stop",
+        ServiceEvent::SessionConnect(_) => "SessionConnect",
+        ServiceEvent::SessionDisconnect(_) => "SessionDisconnect",
+        ServiceEvent::SessionRemoteConnect(_) => "SessionRemoteConnect",
+        ServiceEvent::SessionRemoteDisconnect(_) => "SessionRemoteDisconnect",
+        ServiceEvent::SessionLogon(_) => "SessionLogon",
+        ServiceEvent::SessionLogoff(_) => "SessionLogoff",
+        ServiceEvent::SessionLock(_) => "SessionLock",
+        ServiceEvent::SessionUnlock(_) => "SessionUnlock",
+        ServiceEvent::Custom(_) => "Custom",
+    }
+}
+
+fn record_event<T>(event: &ServiceEvent<T>, paused: &mut bool) {
+    let registry = registry();
+    let name = event_name(event);
+    registry.add_counter(
+        "ceviche_events_total",
+        "Events received by the service.",
+        &[("event", name)],
+        1.0,
+    );
+    if name.starts_with("Session") {
+        registry.add_counter(
+            "ceviche_session_events_total",
+            "Session events received by the service.",
+            &[],
+            1.0,
+        );
+    }
+
+    let pause = match event {
+        ServiceEvent::Pause => Some(true),
+        ServiceEvent::Continue => Some(false),
+        _ => None,
+    };
+    if let Some(pause) = pause.filter(|pause| *pause != *paused) {
+        *paused = pause;
+        registry.set_gauge(
+            "ceviche_paused",
+            "Whether the service is paused.",
+            &[],
+            if pause { 1.0 } else { 0.0 },
+        );
+        registry.add_counter(
+            "ceviche_pause_transitions_total",
+            "Transitions between the paused and running states.",
+            &[("state", if pause { "paused" } else { "running" })],
+            1.0,
+        );
+    }
+}
+
+/// Number of restarts of the service by systemd, when running as a systemd unit.
+#[cfg(target_os = "linux")]
+fn systemd_restarts(unit_name: &str) -> Option<f64> {
+    std::env::var_os("INVOCATION_ID")?;
+    let output = std::process::Command::new("systemctl")
+        .args(["show", "--property=NRestarts", "--value"])
+        .arg(format!("{}.service", unit_name))
+        .output()
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+}
+
+/// File keeping the return code of the service main function until the next start of `unit_name`, next
+/// to its pid file and control socket.
+///
+/// ```rust
+/// use std::path::Path;
+/// use ceviche::metrics::last_exit_code_path;
+///
+/// if cfg!(target_os = "linux") {
+///     assert_eq!(last_exit_code_path("foobar@eu"), Path::new("/run/foobar@eu/foobar@eu.exit"));
+/// }
+/// ```
+pub fn last_exit_code_path(unit_name: &str) -> PathBuf {
+    if cfg!(windows) {
+        std::env::temp_dir().join(format!("{}.exit", unit_name))
+    } else if cfg!(target_os = "macos") {
+        Path::new("/var/run/").join(format!("{}.exit", unit_name))
+    } else {
+        Path::new("/run/")
+            .join(unit_name)
+            .join(format!("{}.exit", unit_name))
+    }
+}
+
+/// Records the return code of the service main function, reported as `ceviche_last_exit_code` when the
+/// service starts again.
+pub fn record_exit_code(unit_name: &str, exit_code: u32) {
+    let path = last_exit_code_path(unit_name);
+    let result = match path.parent() {
+        Some(directory) => fs::create_dir_all(directory),
+        None => Ok(()),
+    }
+    .and_then(|_| fs::write(&path, exit_code.to_string()));
+    if let Err(e) = result {
+        debug!(
+            "Failed to record the exit code in {}: {}",
+            path.display(),
+            e
+        );
+    }
+}
+
+/// Return code of the previous run of the service. The file is removed, so that a run that does not
+/// return is not reported with the return code of an older run.
+fn take_last_exit_code(unit_name: &str) -> Option<f64> {
+    let path = last_exit_code_path(unit_name);
+    let content = fs::read_to_string(&path).ok()?;
+    let _ = fs::remove_file(&path);
+    content.trim().parse().ok()
+}
+
+/// Records the start of the service and the events received on `rx`, returning the receiver the events
+/// are forwarded to. `unit_name` is the name of the systemd units of the service, such as
+/// `<service_name>@<instance>` for an instance of a template.
+pub fn observe<T: Send + 'static>(
+    unit_name: &str,
+    rx: mpsc::Receiver<ServiceEvent<T>>,
+) -> mpsc::Receiver<ServiceEvent<T>> {
+    let registry = registry();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    registry.set_gauge(
+        "ceviche_start_time_seconds",
+        "Start time of the service, in seconds since the UNIX epoch.",
+        &[],
+        now.as_secs() as f64,
+    );
+    registry.set_gauge("ceviche_paused", "Whether the service is paused.", &[], 0.0);
+    #[cfg(target_os = "linux")]
+    if let Some(restarts) = systemd_restarts(unit_name) {
+        registry.set_gauge(
+            "ceviche_restarts",
+            "Restarts of the service by the service manager.",
+            &[],
+            restarts,
+        );
+    }
+    if let Some(exit_code) = take_last_exit_code(unit_name) {
+        registry.set_gauge(
+            "ceviche_last_exit_code",
+            "Return code of the service main function in the previous run of the service.",
+            &[],
+            exit_code,
+        );
+    }
+
+    let (tx, observed_rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut paused = false;
+        for event in rx.iter() {
+            record_event(&event, &mut paused);
+            if tx.send(event).is_err() {
+                break;
+            }
+        }
+    });
+    observed_rx
+}
+
+/// HTTP server exposing the global registry at `/metrics`.
+///
+/// ```rust
+/// use std::io::{Read, Write};
+/// use std::net::TcpStream;
+/// use std::sync::mpsc;
+/// use ceviche::metrics::{observe, registry, MetricsServer};
+/// use ceviche::ServiceEvent;
+///
+/// let (tx, rx) = mpsc::channel::<ServiceEvent<()>>();
+/// let rx = observe("foobar", rx);
+/// tx.send(ServiceEvent::Pause).unwrap();
+/// tx.send(ServiceEvent::Continue).unwrap();
+/// tx.send(ServiceEvent::Stop).unwrap();
+/// for _ in 0..3 {
+///     rx.recv().unwrap();
+/// }
+/// assert_eq!(registry().get("ceviche_events_total", &[("event", "Stop")]), Some(1.0));
+///
+/// let server = MetricsServer::bind("127.0.0.1:0").unwrap();
+/// let address = server.local_addr().unwrap();
+/// server.serve();
+///
+/// let mut stream = TcpStream::connect(address).unwrap();
+/// write!(stream, "GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+/// let mut response = String::new();
+/// stream.read_to_string(&mut response).unwrap();
+/// assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+/// assert!(response.contains("\nceviche_pause_transitions_total{state=\"running\"} 1\n"));
+/// assert!(response.contains("\nceviche_paused 0\n"));
+/// ```
+pub struct MetricsServer {
+    listener: Listener,
+}
+
+impl MetricsServer {
+    /// Listens on a TCP address such as `127.0.0.1:9100`.
+    pub fn bind<A: ToSocketAddrs>(address: A) -> Result<MetricsServer, Error> {
+        Ok(MetricsServer {
+            listener: Listener::bind(address)?,
+        })
+    }
+
+    /// Listens on a Unix domain socket, replacing a socket left behind by a service that is not
+    /// running anymore.
+    #[cfg(unix)]
+    pub fn bind_unix(path: &Path) -> Result<MetricsServer, Error> {
+        Ok(MetricsServer {
+            listener: Listener::bind_unix(path)?,
+        })
+    }
+
+    /// Address of the TCP listener, to find the port chosen by the system when binding port 0.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Path of the Unix domain socket.
+    #[cfg(unix)]
+    pub fn path(&self) -> Option<PathBuf> {
+        self.listener.path()
+    }
+
+    /// Serves the metrics from a background thread.
+    pub fn serve(self) {
+        self.listener.serve(|path| match path {
+            "/metrics" => Response::new("200 OK", "text/plain; version=0.0.4", registry().render()),
+            _ => Response::text("404 Not Found", "not found"),
+        });
+    }
+}
@@ -0,0 +1,196 @@
+//! Pid files locked for as long as the service runs.
+//!
+//! The pid file holds an exclusive lock for the lifetime of the process that wrote it: a pid file that is
+//! not locked was left behind by a process that died, and is stale. The lock is an open file description
+//! lock on Linux, which `read()` probes without taking it, and a `flock` on macOS.
+//!
+//! Pid files are never removed, which would let another instance lock a new file at the same path while
+//! the running one still holds the removed one.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use log::{debug, info};
+
+use crate::Error;
+
+cfg_if! {
+    if #[cfg(target_os = "linux")] {
+        fn lock_request(lock_type: libc::c_int) -> libc::flock {
+            let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+            lock.l_type = lock_type as libc::c_short;
+            lock.l_whence = libc::SEEK_SET as libc::c_short;
+            lock
+        }
+
+        fn try_lock(file: &File) -> bool {
+            let lock = lock_request(libc::F_WRLCK);
+            unsafe { libc::fcntl(file.as_raw_fd(), libc::F_OFD_SETLK, &lock) == 0 }
+        }
+
+        fn is_locked(file: &File) -> bool {
+            let mut lock = lock_request(libc::F_WRLCK);
+            let result = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_OFD_GETLK, &mut lock) };
+            result != 0 || lock.l_type != libc::F_UNLCK as libc::c_short
+        }
+    } else {
+        fn try_lock(file: &File) -> bool {
+            // `is_locked()` holds a shared lock for a moment, retry rather than failing on it.
+            for _ in 0..10 {
+                if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+                    return true;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+            false
+        }
+
+        fn is_locked(file: &File) -> bool {
+            if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_SH | libc::LOCK_NB) } != 0 {
+                return true;
+            }
+            unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_UN) };
+            false
+        }
+    }
+}
+
+/// Whether `file` is still the file at `path`, which another process may have replaced.
+fn is_current(file: &File, path: &Path) -> bool {
+    match (file.metadata(), fs::metadata(path)) {
+        (Ok(opened), Ok(current)) => opened.dev() == current.dev() && opened.ino() == current.ino(),
+        _ => false,
+    }
+}
+
+fn read_pid(file: &mut File) -> Option<u32> {
+    let mut content = String::new();
+    file.seek(SeekFrom::Start(0))
+        .and_then(|_| file.read_to_string(&mut content))
+        .ok()?;
+    content.trim().parse().ok()
+}
+
+/// A pid file holding the pid of the current process, emptied and unlocked when dropped.
+///
+/// Unwinding from a panic drops the pid file like a normal return does. A process killed by a signal or
+/// exiting without running destructors, with `process::exit()` for instance, leaves its pid in the file:
+/// the file is unlocked by the system all the same, and is then stale.
+///
+/// ```rust
+/// use ceviche::pidfile::PidFile;
+///
+/// let path = std::env::temp_dir().join("ceviche-doctest.pid");
+/// let pid_file = PidFile::acquire(&path).unwrap();
+/// assert_eq!(PidFile::read(&path).unwrap(), Some(std::process::id()));
+/// assert!(PidFile::acquire(&path).is_err());
+///
+/// drop(pid_file);
+/// assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+/// assert_eq!(PidFile::read(&path).unwrap(), None);
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+pub struct PidFile {
+    path: PathBuf,
+    // Holds the lock until the pid file is dropped, after the file is emptied.
+    file: File,
+}
+
+impl PidFile {
+    /// Default location of the pid file of a service, `/run/<service_name>/<service_name>.pid`, in the
+    /// runtime directory of the service.
+    pub fn default_path(service_name: &str) -> PathBuf {
+        Path::new("/run/")
+            .join(service_name)
+            .join(format!("{}.pid", service_name))
+    }
+
+    /// Locks the pid file at `path` and writes the pid of the current process to it. Fails when another
+    /// process holds the lock, a stale pid file is replaced.
+    pub fn acquire(path: &Path) -> Result<PidFile, Error> {
+        if let Some(parent) = path.parent().filter(|parent| !parent.exists()) {
+            fs::create_dir_all(parent).map_err(|e| {
+                Error::new(&format!("Failed to create {}: {}", parent.display(), e))
+            })?;
+        }
+
+        let mut file = loop {
+            let mut file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .mode(0o644)
+                .open(path)
+                .map_err(|e| Error::new(&format!("Failed to open {}: {}", path.display(), e)))?;
+
+            if !try_lock(&file) {
+                return Err(Error::new(&format!(
+                    "Already running with pid {}, {} is locked",
+                    read_pid(&mut file)
+                        .map(|pid| pid.to_string())
+                        .unwrap_or_else(|| "unknown".to_string()),
+                    path.display()
+                )));
+            }
+            // The file was replaced between opening and locking it, lock the new one.
+            if is_current(&file, path) {
+                break file;
+            }
+        };
+        if let Some(pid) = read_pid(&mut file) {
+            info!("Replacing stale pid file {} of pid {}", path.display(), pid);
+        }
+
+        file.set_len(0)
+            .and_then(|_| file.seek(SeekFrom::Start(0)))
+            .and_then(|_| writeln!(file, "{}", process::id()))
+            .and_then(|_| file.flush())
+            .map_err(|e| Error::new(&format!("Failed to write {}: {}", path.display(), e)))?;
+
+        Ok(PidFile {
+            path: path.to_path_buf(),
+            file,
+        })
+    }
+
+    /// Reads the pid of the process holding the pid file at `path`, `None` when the file is missing or stale.
+    pub fn read(path: &Path) -> Result<Option<u32>, Error> {
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(Error::new(&format!(
+                    "Failed to open {}: {}",
+                    path.display(),
+                    e
+                )))
+            }
+        };
+
+        if !is_locked(&file) {
+            debug!("Pid file {} is stale", path.display());
+            return Ok(None);
+        }
+        read_pid(&mut file)
+            .map(Some)
+            .ok_or_else(|| Error::new(&format!("Invalid pid file {}", path.display())))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        self.file
+            .set_len(0)
+            .map_err(|e| debug!("Failed to empty {}: {}", self.path.display(), e))
+            .ok();
+    }
+}
@@ -0,0 +1,58 @@
+//! Portable restart policy, applied by every controller when the service is created.
+//!
+//! The policy maps to `Restart=`/`RestartSec=`/`StartLimitBurst=` on Linux, to `KeepAlive`/`ThrottleInterval`
+//! on macOS and to the service failure actions on Windows.
+
+use std::time::Duration;
+
+/// When the service is restarted by the system.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Restart {
+    /// The service is never restarted.
+    Never,
+    /// The service is restarted when it exits with an error or is killed.
+    OnFailure,
+    /// The service is restarted whenever it exits. Windows only restarts services that fail.
+    Always,
+}
+
+/// Restart policy of a service.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RestartPolicy {
+    pub restart: Restart,
+    /// Delay before the service is restarted.
+    pub delay: Duration,
+    /// Maximum number of restarts within `interval`, unlimited when `None`. Not supported on macOS.
+    pub burst: Option<u32>,
+    /// Window over which restarts are counted, the failure count is reset once it elapses.
+    pub interval: Duration,
+}
+
+impl RestartPolicy {
+    pub fn new(restart: Restart) -> RestartPolicy {
+        RestartPolicy {
+            restart,
+            delay: Duration::from_secs(1),
+            burst: None,
+            interval: Duration::from_secs(10),
+        }
+    }
+
+    pub fn never() -> RestartPolicy {
+        RestartPolicy::new(Restart::Never)
+    }
+
+    pub fn on_failure() -> RestartPolicy {
+        RestartPolicy::new(Restart::OnFailure)
+    }
+
+    pub fn always() -> RestartPolicy {
+        RestartPolicy::new(Restart::Always)
+    }
+}
+
+impl Default for RestartPolicy {
+    fn default() -> RestartPolicy {
+        RestartPolicy::never()
+    }
+}
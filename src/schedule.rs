@@ -0,0 +1,106 @@
+//! Portable schedule of periodic services, which run once each time they are triggered.
+//!
+//! The schedule maps to a `.timer` unit on Linux and to `StartCalendarInterval`/`StartInterval` on macOS.
+//! Windows has no equivalent, the schedule is ignored there.
+
+use std::fmt::Write;
+use std::time::Duration;
+
+/// A calendar event, matching every value of the fields left to `None`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CalendarInterval {
+    /// Month, from 1 to 12.
+    pub month: Option<u32>,
+    /// Day of the month, from 1 to 31.
+    pub day: Option<u32>,
+    /// Day of the week, from 0 (Sunday) to 7 (Sunday).
+    pub weekday: Option<u32>,
+    pub hour: Option<u32>,
+    pub minute: Option<u32>,
+}
+
+impl CalendarInterval {
+    /// Every day at `hour:minute`.
+    pub fn daily(hour: u32, minute: u32) -> CalendarInterval {
+        CalendarInterval {
+            hour: Some(hour),
+            minute: Some(minute),
+            ..CalendarInterval::default()
+        }
+    }
+
+    /// Every week on `weekday` at `hour:minute`.
+    pub fn weekly(weekday: u32, hour: u32, minute: u32) -> CalendarInterval {
+        CalendarInterval {
+            weekday: Some(weekday),
+            ..CalendarInterval::daily(hour, minute)
+        }
+    }
+
+    /// Formats the event as a systemd calendar event, for `OnCalendar=`.
+    ///
+    /// ```rust
+    /// use ceviche::schedule::CalendarInterval;
+    ///
+    /// assert_eq!(CalendarInterval::daily(3, 30).to_on_calendar(), "*-*-* 03:30:00");
+    /// assert_eq!(CalendarInterval::weekly(1, 0, 0).to_on_calendar(), "Mon *-*-* 00:00:00");
+    /// assert_eq!(CalendarInterval { day: Some(1), minute: Some(15), ..Default::default() }.to_on_calendar(), "*-*-01 *:15:00");
+    /// ```
+    pub fn to_on_calendar(&self) -> String {
+        const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+        let field = |value: Option<u32>| match value {
+            Some(value) => format!("{:02}", value),
+            None => "*".to_string(),
+        };
+
+        let mut event = String::new();
+        if let Some(weekday) = self.weekday {
+            let _ = write!(event, "{} ", WEEKDAYS[weekday as usize % 7]);
+        }
+        let _ = write!(
+            event,
+            "*-{}-{} {}:{}:00",
+            field(self.month),
+            field(self.day),
+            field(self.hour),
+            field(self.minute)
+        );
+        event
+    }
+}
+
+/// When a periodic service is started.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Schedule {
+    /// Calendar events (`OnCalendar=`, `StartCalendarInterval`).
+    pub calendar: Vec<CalendarInterval>,
+    /// Delay after boot (`OnBootSec=`). launchd runs the job when it is loaded instead (`RunAtLoad`).
+    pub on_boot: Option<Duration>,
+    /// Interval between runs (`OnUnitActiveSec=`, `StartInterval`). systemd counts it from the previous
+    /// run, so it needs `on_boot` or `calendar` to run the service a first time.
+    pub interval: Option<Duration>,
+    /// Runs the service when the system starts if a run was missed while it was down (`Persistent=`),
+    /// only supported by systemd.
+    pub persistent: bool,
+    /// Random delay added to each run (`RandomizedDelaySec=`), only supported by systemd.
+    pub randomized_delay: Option<Duration>,
+}
+
+impl Schedule {
+    /// Runs the service at the given calendar events.
+    pub fn calendar(calendar: Vec<CalendarInterval>) -> Schedule {
+        Schedule {
+            calendar,
+            ..Schedule::default()
+        }
+    }
+
+    /// Runs the service shortly after boot, then every `interval`.
+    pub fn interval(interval: Duration) -> Schedule {
+        Schedule {
+            on_boot: Some(Duration::from_secs(60)),
+            interval: Some(interval),
+            ..Schedule::default()
+        }
+    }
+}
@@ -0,0 +1,328 @@
+//! Rendering of Windows service installation scripts.
+//!
+//! This module does not depend on the host system, scripts can be generated on any platform.
+
+use std::fmt::Write;
+use std::iter::once;
+
+use crate::definition::ServiceDefinition;
+use crate::launchd::xml_escape;
+use crate::restart::{Restart, RestartPolicy};
+
+/// Quotes a command line argument following the rules of `CommandLineToArgvW`.
+///
+/// ```rust
+/// use ceviche::scm::quote_arg;
+///
+/// assert_eq!(quote_arg(r"C:\foobar\foobar.exe"), r"C:\foobar\foobar.exe");
+/// assert_eq!(quote_arg(r"C:\Program Files\foobar\"), r#""C:\Program Files\foobar\\""#);
+/// assert_eq!(quote_arg(r#"say "hi""#), r#""say \"hi\"""#);
+/// ```
+pub fn quote_arg(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '"']) {
+        return arg.to_string();
+    }
+    force_quote_arg(arg)
+}
+
+/// Quotes a command line argument following the rules of `CommandLineToArgvW`, even when it does not
+/// need to be quoted.
+fn force_quote_arg(arg: &str) -> String {
+    let mut quoted = String::from("\"");
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                quoted.push_str(&"\\".repeat(backslashes * 2 + 1));
+                backslashes = 0;
+            }
+            _ => {
+                quoted.push_str(&"\\".repeat(backslashes));
+                backslashes = 0;
+            }
+        }
+        if c != '\\' {
+            quoted.push(c);
+        }
+    }
+    quoted.push_str(&"\\".repeat(backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+/// Quotes a value for `sc.exe` or `reg.exe` in a batch script.
+///
+/// The value is quoted for the command line parser of the program, then escaped for `cmd`, which toggles
+/// its own quoting on every `"` and does not know `\"`: metacharacters it sees outside quotes are escaped
+/// with `^`, and `%` is doubled everywhere. Line breaks would end the command, they are replaced with spaces.
+fn sc_quote(value: &str) -> String {
+    let value = value.replace(['\r', '\n'], " ");
+    let mut escaped = String::new();
+    let mut quoted = false;
+    for c in force_quote_arg(&value).chars() {
+        match c {
+            '"' => quoted = !quoted,
+            '%' => escaped.push('%'),
+            '&' | '|' | '<' | '>' | '^' | '(' | ')' if !quoted => escaped.push('^'),
+            _ => {}
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn executable(definition: &ServiceDefinition) -> &str {
+    definition
+        .windows_executable
+        .as_deref()
+        .unwrap_or(&definition.executable)
+}
+
+fn arguments(definition: &ServiceDefinition) -> String {
+    let arguments: Vec<String> = definition
+        .arguments
+        .iter()
+        .map(|arg| quote_arg(arg))
+        .collect();
+    arguments.join(" ")
+}
+
+fn command_line(definition: &ServiceDefinition) -> String {
+    let command_line: Vec<String> = once(executable(definition))
+        .chain(definition.arguments.iter().map(|arg| arg.as_str()))
+        .map(quote_arg)
+        .collect();
+    command_line.join(" ")
+}
+
+/// Names of the services the service requires, the only kind of dependency Windows supports.
+fn required_services(definition: &ServiceDefinition) -> Vec<&str> {
+    definition
+        .dependencies
+        .iter()
+        .filter(|dependency| dependency.is_required())
+        .map(|dependency| dependency.name.as_str())
+        .collect()
+}
+
+/// Failure actions for the first three failures, the last one repeating for subsequent failures.
+fn failure_actions(policy: &RestartPolicy) -> Vec<bool> {
+    match policy.burst {
+        Some(burst) => (0..3).map(|index| index < burst).collect(),
+        None => vec![true; 3],
+    }
+}
+
+/// Generates a batch script that installs the service with `sc.exe`.
+///
+/// ```rust
+/// use ceviche::definition::ServiceDefinition;
+/// use ceviche::restart::RestartPolicy;
+/// use ceviche::scm::sc_script;
+///
+/// let mut definition = ServiceDefinition::new("foobar", "FooBar Service", "This is the FooBar service", r"C:\Program Files\FooBar\foobar.exe");
+/// definition.restart = RestartPolicy::always();
+/// definition.restart.burst = Some(2);
+///
+/// assert_eq!(sc_script(&definition), r#"@echo off
+/// sc.exe create "foobar" binPath= "\"C:\Program Files\FooBar\foobar.exe\"" DisplayName= "FooBar Service" start= auto
+/// if errorlevel 1 exit /b 1
+/// sc.exe description "foobar" "This is the FooBar service"
+/// sc.exe failure "foobar" reset= 10 actions= restart/1000/restart/1000/""/0
+/// sc.exe failureflag "foobar" 1
+/// "#.replace('\n', "\r\n"));
+///
+/// // cmd sees the path between the escaped quotes outside its own quoting.
+/// definition.executable = r"C:\Program Files\Foo&Bar\foobar.exe".to_string();
+/// definition.environment = vec![("FOOBAR_HOME".to_string(), r"C:\Foo&Bar 100%".to_string())];
+/// let script = sc_script(&definition);
+/// assert!(script.contains(r#" binPath= "\"C:\Program Files\Foo^&Bar\foobar.exe\"" "#));
+/// assert!(script.contains(r#" /d "FOOBAR_HOME=C:\Foo&Bar 100%%" /f"#));
+/// ```
+pub fn sc_script(definition: &ServiceDefinition) -> String {
+    let name = sc_quote(&definition.service_name);
+    let mut script = String::from("@echo off\r\n");
+
+    let _ = write!(
+        script,
+        "sc.exe create {} binPath= {} DisplayName= {} start= auto",
+        name,
+        sc_quote(&command_line(definition)),
+        sc_quote(&definition.display_name)
+    );
+    if let Some(ref user) = definition.user {
+        let _ = write!(script, " obj= {}", sc_quote(user));
+    }
+    let dependencies = required_services(definition);
+    if !dependencies.is_empty() {
+        let _ = write!(script, " depend= {}", sc_quote(&dependencies.join("/")));
+    }
+    script.push_str("\r\nif errorlevel 1 exit /b 1\r\n");
+
+    let _ = write!(
+        script,
+        "sc.exe description {} {}\r\n",
+        name,
+        sc_quote(&definition.description)
+    );
+
+    if !definition.environment.is_empty() {
+        let environment: Vec<String> = definition
+            .environment
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        let _ = write!(
+            script,
+            "reg.exe add {} /v Environment /t REG_MULTI_SZ",
+            sc_quote(&format!(
+                "HKLM\\SYSTEM\\CurrentControlSet\\Services\\{}",
+                definition.service_name
+            ))
+        );
+        // reg.exe splits the data on `\0`, another separator is needed when a variable contains it.
+        let separator = match environment.iter().any(|entry| entry.contains("\\0")) {
+            true => ['~', '#', '*', '+', ';', ',']
+                .into_iter()
+                .find(|separator| !environment.iter().any(|entry| entry.contains(*separator))),
+            false => None,
+        };
+        match separator {
+            Some(separator) => {
+                let _ = write!(
+                    script,
+                    " /s {} /d {}",
+                    separator,
+                    sc_quote(&environment.join(&separator.to_string()))
+                );
+            }
+            None => {
+                let _ = write!(script, " /d {}", sc_quote(&environment.join("\\0")));
+            }
+        }
+        script.push_str(" /f\r\n");
+    }
+
+    let policy = &definition.restart;
+    if policy.restart != Restart::Never {
+        let delay = policy.delay.as_millis();
+        let mut actions: Vec<String> = Vec::new();
+        match policy.burst {
+            Some(burst) => {
+                actions.extend((0..burst).map(|_| format!("restart/{}", delay)));
+                actions.push("\"\"/0".to_string());
+            }
+            None => actions.push(format!("restart/{}", delay)),
+        }
+        let _ = write!(
+            script,
+            "sc.exe failure {} reset= {} actions= {}\r\n",
+            name,
+            policy.interval.as_secs(),
+            actions.join("/")
+        );
+        if policy.restart == Restart::Always {
+            let _ = write!(script, "sc.exe failureflag {} 1\r\n", name);
+        }
+    }
+
+    script
+}
+
+/// Generates a WiX include with the `ServiceInstall` and `ServiceControl` elements of the service.
+///
+/// Failure actions use the `util:ServiceConfig` element of the WiX utility extension, which only
+/// configures the first three failures.
+///
+/// ```rust
+/// use ceviche::definition::ServiceDefinition;
+/// use ceviche::dependency::Dependency;
+/// use ceviche::scm::wix_include;
+///
+/// let mut definition = ServiceDefinition::new("foobar", "FooBar & Co", "This is the FooBar service", r"C:\foobar\foobar.exe");
+/// definition.arguments = vec!["--verbose".to_string()];
+/// definition.dependencies = vec![Dependency::requires("postgresql"), Dependency::wants("network-online.target")];
+///
+/// let wxi = wix_include(&definition);
+/// assert!(wxi.contains(r#"<ServiceInstall Id="foobar" Name="foobar" DisplayName="FooBar &amp; Co""#));
+/// assert!(wxi.contains(r#"Arguments="--verbose">"#));
+/// assert!(wxi.contains("\n    <ServiceDependency Id=\"postgresql\"/>\n  </ServiceInstall>\n"));
+/// assert!(wxi.contains(r#"<ServiceControl Id="foobar" Name="foobar" Start="install" Stop="both" Remove="uninstall" Wait="yes"/>"#));
+/// ```
+pub fn wix_include(definition: &ServiceDefinition) -> String {
+    let id: String = definition
+        .service_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let name = xml_escape(&definition.service_name);
+
+    let mut wxi = String::from(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<Include xmlns="http://schemas.microsoft.com/wix/2006/wi" xmlns:util="http://schemas.microsoft.com/wix/UtilExtension">
+"#,
+    );
+
+    let _ = write!(
+        wxi,
+        r#"  <ServiceInstall Id="{}" Name="{}" DisplayName="{}" Description="{}" Type="ownProcess" Start="auto" ErrorControl="normal""#,
+        id,
+        name,
+        xml_escape(&definition.display_name),
+        xml_escape(&definition.description)
+    );
+    if !definition.arguments.is_empty() {
+        let _ = write!(
+            wxi,
+            r#" Arguments="{}""#,
+            xml_escape(&arguments(definition))
+        );
+    }
+    if let Some(ref user) = definition.user {
+        let _ = write!(wxi, r#" Account="{}""#, xml_escape(user));
+    }
+
+    let mut children: Vec<String> = required_services(definition)
+        .into_iter()
+        .map(|dependency| format!(r#"<ServiceDependency Id="{}"/>"#, xml_escape(dependency)))
+        .collect();
+    let policy = &definition.restart;
+    if policy.restart != Restart::Never {
+        let actions: Vec<&str> = failure_actions(policy)
+            .into_iter()
+            .map(|restart| if restart { "restart" } else { "none" })
+            .collect();
+        children.push(format!(
+            r#"<util:ServiceConfig FirstFailureActionType="{}" SecondFailureActionType="{}" ThirdFailureActionType="{}" RestartServiceDelayInSeconds="{}" ResetPeriodInDays="{}"/>"#,
+            actions[0],
+            actions[1],
+            actions[2],
+            policy.delay.as_secs(),
+            policy.interval.as_secs().div_ceil(86400)
+        ));
+    }
+    if children.is_empty() {
+        wxi.push_str("/>\n");
+    } else {
+        let _ = writeln!(wxi, ">");
+        for child in children {
+            let _ = writeln!(wxi, "    {}", child);
+        }
+        wxi.push_str("  </ServiceInstall>\n");
+    }
+
+    let _ = writeln!(
+        wxi,
+        r#"  <ServiceControl Id="{}" Name="{}" Start="install" Stop="both" Remove="uninstall" Wait="yes"/>"#,
+        id, name
+    );
+    wxi.push_str("</Include>\n");
+    wxi
+}
@@ -0,0 +1,75 @@
+//! Unix domain socket helpers shared by the control socket and the single-instance guard.
+
+use std::fs::{self, DirBuilder};
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+use std::process;
+#[cfg(target_os = "linux")]
+use std::{io, os::unix::io::AsRawFd, os::unix::net::UnixStream};
+
+use crate::Error;
+
+/// Binds a Unix domain socket at `path` that only the current user can connect to. The socket is bound
+/// in a private directory next to `path` and only moved in place once its permissions are restricted.
+pub(crate) fn bind_private(path: &Path) -> Result<UnixListener, Error> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| Error::new(&format!("Invalid socket path {}", path.display())))?;
+    let parent = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    fs::create_dir_all(parent)
+        .map_err(|e| Error::new(&format!("Failed to create {}: {}", parent.display(), e)))?;
+
+    let dir = parent.join(format!(
+        ".{}.{}",
+        file_name.to_string_lossy(),
+        process::id()
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    DirBuilder::new()
+        .mode(0o700)
+        .create(&dir)
+        .map_err(|e| Error::new(&format!("Failed to create {}: {}", dir.display(), e)))?;
+
+    let bind = || {
+        let private_path = dir.join(file_name);
+        let listener = UnixListener::bind(&private_path)
+            .map_err(|e| Error::new(&format!("Failed to bind {}: {}", path.display(), e)))?;
+        fs::set_permissions(&private_path, fs::Permissions::from_mode(0o600)).map_err(|e| {
+            Error::new(&format!(
+                "Failed to set permissions of {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        fs::rename(&private_path, path)
+            .map_err(|e| Error::new(&format!("Failed to bind {}: {}", path.display(), e)))?;
+        Ok(listener)
+    };
+    let result = bind();
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+/// User id of the process connected to `stream`.
+#[cfg(target_os = "linux")]
+pub(crate) fn peer_uid(stream: &UnixStream) -> io::Result<u32> {
+    let mut credentials: libc::ucred = unsafe { std::mem::zeroed() };
+    let mut length = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut credentials as *mut libc::ucred as *mut libc::c_void,
+            &mut length,
+        )
+    };
+    match result {
+        0 => Ok(credentials.uid),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
@@ -0,0 +1,880 @@
+//! Rendering of systemd unit files.
+//!
+//! This module does not depend on the host system, units can be generated and inspected on any platform.
+
+use std::fmt::{self, Write};
+use std::iter::once;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::controller::INSTANCE_ENV;
+use crate::definition::{ListenSocket, ServiceDefinition};
+use crate::dependency::{Dependency, Order, Strength};
+use crate::restart::{Restart, RestartPolicy};
+
+/// A systemd unit file: a list of `[Section]` headers, each followed by `Key=Value` entries.
+///
+/// Entries keep their insertion order and a key can be repeated, as systemd allows for
+/// settings like `Environment=` or `EnvironmentFile=`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UnitFile {
+    sections: Vec<(String, Vec<(String, String)>)>,
+}
+
+impl UnitFile {
+    pub fn new() -> UnitFile {
+        UnitFile::default()
+    }
+
+    /// Appends `key=value` to `section`, creating the section if needed.
+    pub fn add(&mut self, section: &str, key: &str, value: &str) {
+        let index = match self.sections.iter().position(|(name, _)| name == section) {
+            Some(index) => index,
+            None => {
+                self.sections.push((section.to_string(), Vec::new()));
+                self.sections.len() - 1
+            }
+        };
+        self.sections[index]
+            .1
+            .push((key.to_string(), value.to_string()));
+    }
+
+    /// Renders the unit file.
+    ///
+    /// ```rust
+    /// use ceviche::systemd::UnitFile;
+    ///
+    /// let mut unit = UnitFile::new();
+    /// unit.add("Unit", "Description", "foobar");
+    /// unit.add("Service", "ExecStart", "/usr/bin/foobar");
+    ///
+    /// assert_eq!(unit.render(), "[Unit]\nDescription=foobar\n\n[Service]\nExecStart=/usr/bin/foobar\n");
+    /// ```
+    pub fn render(&self) -> String {
+        let mut content = String::new();
+        for (index, (section, entries)) in self.sections.iter().enumerate() {
+            if index > 0 {
+                content.push('\n');
+            }
+            let _ = writeln!(content, "[{}]", section);
+            for (key, value) in entries {
+                let _ = writeln!(content, "{}={}", key, value);
+            }
+        }
+        content
+    }
+}
+
+/// Quotes a `NAME=value` assignment for use with `Environment=`.
+///
+/// The assignment is wrapped in double quotes so that whitespace is preserved, backslashes, quotes
+/// and control characters are escaped C-style and `%` is doubled so it is not taken as a specifier.
+/// systemd does not perform variable expansion in `Environment=`, so `$` is kept as is.
+///
+/// ```rust
+/// use ceviche::systemd::escape_environment;
+///
+/// assert_eq!(escape_environment("NAME", "foo bar"), r#""NAME=foo bar""#);
+/// assert_eq!(escape_environment("NAME", r#"say "hi""#), r#""NAME=say \"hi\"""#);
+/// assert_eq!(escape_environment("NAME", "$HOME/100%"), r#""NAME=$HOME/100%%""#);
+/// assert_eq!(escape_environment("NAME", "a\\b\nc"), r#""NAME=a\\b\nc""#);
+/// ```
+pub fn escape_environment(name: &str, value: &str) -> String {
+    let mut escaped = String::with_capacity(name.len() + value.len() + 3);
+    escaped.push('"');
+    for c in name.chars().chain(once('=')).chain(value.chars()) {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            '%' => escaped.push_str("%%"),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Quotes a command line argument for use with `ExecStart=`.
+///
+/// Arguments without whitespace, quotes or backslashes are kept as is, others are wrapped in double quotes.
+/// `%` and `$` are doubled so they are not taken as a specifier or a variable reference.
+///
+/// ```rust
+/// use ceviche::systemd::quote_exec_arg;
+///
+/// assert_eq!(quote_exec_arg("/usr/bin/foobar"), "/usr/bin/foobar");
+/// assert_eq!(quote_exec_arg("/opt/foo bar/foobar"), r#""/opt/foo bar/foobar""#);
+/// assert_eq!(quote_exec_arg("$HOME/50%"), "$$HOME/50%%");
+/// ```
+pub fn quote_exec_arg(arg: &str) -> String {
+    let quoted = arg.is_empty()
+        || arg.contains(|c: char| c.is_whitespace() || c == '"' || c == '\'' || c == '\\');
+    let mut escaped = String::with_capacity(arg.len() + 2);
+    if quoted {
+        escaped.push('"');
+    }
+    for c in arg.chars() {
+        match c {
+            '"' if quoted => escaped.push_str("\\\""),
+            '\\' if quoted => escaped.push_str("\\\\"),
+            '\n' if quoted => escaped.push_str("\\n"),
+            '%' => escaped.push_str("%%"),
+            '$' => escaped.push_str("$$"),
+            c => escaped.push(c),
+        }
+    }
+    if quoted {
+        escaped.push('"');
+    }
+    escaped
+}
+
+/// Quotes a path for use in a list of paths such as `ReadWritePaths=`, like `quote_exec_arg()` but keeping
+/// `%` specifiers and `$`, which is not expanded in paths.
+fn quote_path(path: &str) -> String {
+    if !path.is_empty()
+        && !path.contains(|c: char| c.is_whitespace() || c == '"' || c == '\'' || c == '\\')
+    {
+        return path.to_string();
+    }
+
+    let mut escaped = String::with_capacity(path.len() + 2);
+    escaped.push('"');
+    for c in path.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Escapes a string for use in a unit name, such as the instance name of a template unit, like
+/// `systemd-escape` does: `/` becomes `-` and other characters than ASCII letters, digits, `:`, `_` and
+/// `.` are written as `\xNN`. The service reads the unescaped instance name from `%I`.
+///
+/// ```rust
+/// use ceviche::systemd::escape_unit_name;
+///
+/// assert_eq!(escape_unit_name("eu"), "eu");
+/// assert_eq!(escape_unit_name("eu-west 1"), r"eu\x2dwest\x201");
+/// assert_eq!(escape_unit_name(".hidden/dir"), r"\x2ehidden-dir");
+/// ```
+pub fn escape_unit_name(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for (index, byte) in value.bytes().enumerate() {
+        match byte {
+            b'/' => escaped.push('-'),
+            b'.' if index == 0 => escaped.push_str("\\x2e"),
+            b if b.is_ascii_alphanumeric() || b == b':' || b == b'_' || b == b'.' => {
+                escaped.push(b as char)
+            }
+            b => {
+                let _ = write!(escaped, "\\x{:02x}", b);
+            }
+        }
+    }
+    escaped
+}
+
+/// Directory of `path` relative to `/run`, which systemd creates writable for the service with
+/// `RuntimeDirectory=`. `None` when `path` is not in a subdirectory of `/run`.
+pub(crate) fn runtime_directory(path: &Path) -> Option<String> {
+    let directory = path.parent()?.strip_prefix("/run").ok()?;
+    match directory.as_os_str().is_empty() {
+        true => None,
+        false => Some(directory.to_string_lossy().into_owned()),
+    }
+}
+
+/// Builds the service unit of `definition`.
+///
+/// The unit of a template (`definition.template`) passes the instance name to the service in the
+/// `CEVICHE_INSTANCE` environment variable. The directory of the pid file is kept writable despite the
+/// hardening of the unit: `RuntimeDirectory=` creates a directory under `/run`, other directories are added
+/// to `ReadWritePaths=` with `ProtectSystem=strict`.
+///
+/// ```rust
+/// use ceviche::definition::ServiceDefinition;
+/// use ceviche::dependency::Dependency;
+/// use ceviche::systemd::{service_unit, Hardening, HardeningProfile};
+///
+/// let mut definition = ServiceDefinition::new("foobar", "FooBar Service", "This is the FooBar service", "/usr/bin/foobar");
+/// definition.pid_file = Some("/run/foobar.pid".to_string());
+/// definition.dependencies = vec![Dependency::wants("network-online.target"), Dependency::requires("postgresql")];
+///
+/// let unit = service_unit(&definition).render();
+/// assert!(unit.starts_with("\
+/// [Unit]
+/// Description=FooBar Service
+/// Wants=network-online.target
+/// After=network-online.target
+/// Requires=postgresql.service
+/// After=postgresql.service
+///
+/// [Service]
+/// Type=forking
+/// PIDFile=/run/foobar.pid
+/// ExecStart=/usr/bin/foobar
+/// "));
+///
+/// definition.hardening = Hardening::new(HardeningProfile::Strict);
+/// definition.pid_file = Some("/run/foobar/foobar.pid".to_string());
+/// assert!(service_unit(&definition).render().contains("RuntimeDirectory=foobar\n"));
+/// definition.pid_file = Some("/var/run/foobar.pid".to_string());
+/// assert!(service_unit(&definition).render().contains("ReadWritePaths=/var/run\n"));
+/// ```
+pub fn service_unit(definition: &ServiceDefinition) -> UnitFile {
+    let mut unit = UnitFile::new();
+    unit.add("Unit", "Description", &definition.display_name);
+    add_dependencies(&definition.dependencies, &mut unit);
+
+    if definition.schedule.is_some() {
+        unit.add("Service", "Type", "oneshot");
+    } else if let Some(ref path) = definition.pid_file {
+        unit.add("Service", "Type", "forking");
+        unit.add("Service", "PIDFile", path);
+    }
+    let command_line: Vec<String> = once(&definition.executable)
+        .chain(definition.arguments.iter())
+        .map(|arg| quote_exec_arg(arg))
+        .collect();
+    unit.add("Service", "ExecStart", &command_line.join(" "));
+    if let Some(ref path) = definition.working_directory {
+        unit.add("Service", "WorkingDirectory", path);
+    }
+    for (name, value) in &definition.environment {
+        unit.add("Service", "Environment", &escape_environment(name, value));
+    }
+    if definition.template {
+        unit.add(
+            "Service",
+            "Environment",
+            &format!("\"{}=%I\"", INSTANCE_ENV),
+        );
+    }
+    for path in &definition.environment_files {
+        unit.add("Service", "EnvironmentFile", path);
+    }
+    if let Some(ref user) = definition.user {
+        unit.add("Service", "User", user);
+    }
+    if let Some(ref group) = definition.group {
+        unit.add("Service", "Group", group);
+    }
+    if !definition.supplementary_groups.is_empty() {
+        unit.add(
+            "Service",
+            "SupplementaryGroups",
+            &definition.supplementary_groups.join(" "),
+        );
+    }
+    if definition.dynamic_user {
+        unit.add("Service", "DynamicUser", "yes");
+    }
+    let mut hardening = definition.hardening.clone();
+    if let Some(ref path) = definition.pid_file {
+        hardening.allow_writes_to(path);
+    }
+    hardening.apply(&mut unit);
+    match definition.schedule {
+        // Oneshot services can only be restarted on failure, and are started by their timer.
+        Some(_) if definition.restart.restart == Restart::Always => {
+            let mut restart = definition.restart.clone();
+            restart.restart = Restart::OnFailure;
+            add_restart_policy(&restart, &mut unit);
+        }
+        Some(_) => add_restart_policy(&definition.restart, &mut unit),
+        None => {
+            add_restart_policy(&definition.restart, &mut unit);
+            unit.add("Install", "WantedBy", "multi-user.target");
+        }
+    }
+
+    unit
+}
+
+/// Builds the timer unit of `definition`, if the service has a schedule.
+///
+/// ```rust
+/// use std::time::Duration;
+/// use ceviche::definition::ServiceDefinition;
+/// use ceviche::schedule::{CalendarInterval, Schedule};
+/// use ceviche::systemd::{service_unit, timer_unit};
+///
+/// let mut definition = ServiceDefinition::new("cleanup", "Cleanup", "Removes stale files", "/usr/bin/cleanup");
+/// let mut schedule = Schedule::calendar(vec![CalendarInterval::daily(3, 0)]);
+/// schedule.persistent = true;
+/// schedule.randomized_delay = Some(Duration::from_secs(600));
+/// definition.schedule = Some(schedule);
+///
+/// assert_eq!(service_unit(&definition).render(), "\
+/// [Unit]
+/// Description=Cleanup
+///
+/// [Service]
+/// Type=oneshot
+/// ExecStart=/usr/bin/cleanup
+/// ");
+/// assert_eq!(timer_unit(&definition).unwrap().render(), "\
+/// [Unit]
+/// Description=Cleanup
+///
+/// [Timer]
+/// OnCalendar=*-*-* 03:00:00
+/// Persistent=true
+/// RandomizedDelaySec=600
+///
+/// [Install]
+/// WantedBy=timers.target
+/// ");
+/// ```
+pub fn timer_unit(definition: &ServiceDefinition) -> Option<UnitFile> {
+    let schedule = definition.schedule.as_ref()?;
+
+    let mut unit = UnitFile::new();
+    unit.add("Unit", "Description", &definition.display_name);
+    for event in &schedule.calendar {
+        unit.add("Timer", "OnCalendar", &event.to_on_calendar());
+    }
+    if let Some(delay) = schedule.on_boot {
+        unit.add("Timer", "OnBootSec", &format_seconds(delay));
+    }
+    if let Some(interval) = schedule.interval {
+        unit.add("Timer", "OnUnitActiveSec", &format_seconds(interval));
+    }
+    if schedule.persistent {
+        unit.add("Timer", "Persistent", "true");
+    }
+    if let Some(delay) = schedule.randomized_delay {
+        unit.add("Timer", "RandomizedDelaySec", &format_seconds(delay));
+    }
+    unit.add("Install", "WantedBy", "timers.target");
+
+    Some(unit)
+}
+
+/// Builds the socket unit of `definition`, if the service has sockets.
+pub fn socket_unit(definition: &ServiceDefinition) -> Option<UnitFile> {
+    if definition.sockets.is_empty() {
+        return None;
+    }
+
+    let mut unit = UnitFile::new();
+    unit.add("Unit", "Description", &definition.display_name);
+    for socket in &definition.sockets {
+        match socket {
+            ListenSocket::Stream(address) => unit.add("Socket", "ListenStream", address),
+            ListenSocket::Datagram(address) => unit.add("Socket", "ListenDatagram", address),
+        }
+    }
+    unit.add("Install", "WantedBy", "sockets.target");
+
+    Some(unit)
+}
+
+/// Generates a sysusers.d snippet that creates `user` as a system user, with `group` as its primary
+/// group and membership in `supplementary_groups`.
+///
+/// ```rust
+/// use ceviche::systemd::sysusers_snippet;
+///
+/// assert_eq!(
+///     sysusers_snippet("foobar", None, &[], "FooBar Service"),
+///     "u foobar - \"FooBar Service\"\n"
+/// );
+/// assert_eq!(
+///     sysusers_snippet("foobar", Some("daemons"), &["adm".to_string()], "FooBar Service"),
+///     "g daemons -\nu foobar -:daemons \"FooBar Service\"\nm foobar adm\n"
+/// );
+/// ```
+pub fn sysusers_snippet(
+    user: &str,
+    group: Option<&str>,
+    supplementary_groups: &[String],
+    description: &str,
+) -> String {
+    let mut snippet = String::new();
+    let description = description.replace('"', "'");
+    match group {
+        Some(group) if group != user => {
+            let _ = writeln!(snippet, "g {} -", group);
+            let _ = writeln!(snippet, "u {} -:{} \"{}\"", user, group, description);
+        }
+        _ => {
+            let _ = writeln!(snippet, "u {} - \"{}\"", user, description);
+        }
+    }
+    for group in supplementary_groups {
+        let _ = writeln!(snippet, "m {} {}", user, group);
+    }
+    snippet
+}
+
+/// Hardening presets for generated units.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum HardeningProfile {
+    /// No sandboxing directive is added.
+    #[default]
+    None,
+    /// Protects the system and kernel while keeping the service able to write where it usually does.
+    Basic,
+    /// Read-only file system, no capabilities and a restricted set of system calls. Writable paths
+    /// must be declared through `read_write_paths` or the `*_directory` settings.
+    Strict,
+}
+
+/// Value of `ProtectSystem=`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProtectSystem {
+    No,
+    Yes,
+    Full,
+    Strict,
+}
+
+impl fmt::Display for ProtectSystem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProtectSystem::No => write!(f, "no"),
+            ProtectSystem::Yes => write!(f, "yes"),
+            ProtectSystem::Full => write!(f, "full"),
+            ProtectSystem::Strict => write!(f, "strict"),
+        }
+    }
+}
+
+/// Value of `ProtectHome=`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProtectHome {
+    No,
+    Yes,
+    ReadOnly,
+    Tmpfs,
+}
+
+impl fmt::Display for ProtectHome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProtectHome::No => write!(f, "no"),
+            ProtectHome::Yes => write!(f, "yes"),
+            ProtectHome::ReadOnly => write!(f, "read-only"),
+            ProtectHome::Tmpfs => write!(f, "tmpfs"),
+        }
+    }
+}
+
+/// Sandboxing settings of a service unit.
+///
+/// Settings left to `None` take the value of the selected `profile`, setting them overrides the preset.
+///
+/// ```rust
+/// use ceviche::systemd::{Hardening, HardeningProfile, ProtectHome, UnitFile};
+///
+/// let mut hardening = Hardening::new(HardeningProfile::Strict);
+/// hardening.protect_home = Some(ProtectHome::ReadOnly);
+/// hardening.state_directory = Some("foobar".to_string());
+/// hardening.read_write_paths = vec!["/srv/foo bar".to_string(), "-/var/cache/foobar".to_string()];
+///
+/// let mut unit = UnitFile::new();
+/// hardening.apply(&mut unit);
+/// let content = unit.render();
+///
+/// assert!(content.contains("ProtectSystem=strict\n"));
+/// assert!(content.contains("ProtectHome=read-only\n"));
+/// assert!(content.contains("CapabilityBoundingSet=\n"));
+/// assert!(content.contains("StateDirectory=foobar\n"));
+/// assert!(content.contains("ReadWritePaths=\"/srv/foo bar\" -/var/cache/foobar\n"));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Hardening {
+    pub profile: HardeningProfile,
+    /// `ProtectSystem=`
+    pub protect_system: Option<ProtectSystem>,
+    /// `ProtectHome=`
+    pub protect_home: Option<ProtectHome>,
+    /// `PrivateTmp=`
+    pub private_tmp: Option<bool>,
+    /// `PrivateDevices=`
+    pub private_devices: Option<bool>,
+    /// `NoNewPrivileges=`
+    pub no_new_privileges: Option<bool>,
+    /// `ProtectKernelTunables=`, `ProtectKernelModules=` and `ProtectKernelLogs=`
+    pub protect_kernel: Option<bool>,
+    /// `ProtectControlGroups=`
+    pub protect_control_groups: Option<bool>,
+    /// `RestrictSUIDSGID=`, `RestrictRealtime=` and `LockPersonality=`
+    pub restrict_privileges: Option<bool>,
+    /// `CapabilityBoundingSet=`, an empty list drops every capability.
+    pub capability_bounding_set: Option<Vec<String>>,
+    /// `SystemCallFilter=`, entries can be system calls or groups such as `@system-service`.
+    pub system_call_filter: Option<Vec<String>>,
+    /// `ReadWritePaths=`
+    pub read_write_paths: Vec<String>,
+    /// `ReadOnlyPaths=`
+    pub read_only_paths: Vec<String>,
+    /// `StateDirectory=`, a writable directory created under `/var/lib`.
+    pub state_directory: Option<String>,
+    /// `LogsDirectory=`, a writable directory created under `/var/log`.
+    pub logs_directory: Option<String>,
+    /// `RuntimeDirectory=`, a writable directory created under `/run` and removed when the service stops,
+    /// except when systemd restarts it (`RuntimeDirectoryPreserve=restart`).
+    pub runtime_directory: Option<String>,
+}
+
+impl Hardening {
+    pub fn new(profile: HardeningProfile) -> Hardening {
+        Hardening {
+            profile,
+            ..Hardening::default()
+        }
+    }
+
+    /// `ProtectSystem=` of the unit, from the setting or the profile.
+    fn effective_protect_system(&self) -> Option<ProtectSystem> {
+        match self.profile {
+            _ if self.protect_system.is_some() => self.protect_system,
+            HardeningProfile::None => None,
+            HardeningProfile::Basic => Some(ProtectSystem::Full),
+            HardeningProfile::Strict => Some(ProtectSystem::Strict),
+        }
+    }
+
+    /// Keeps the directory of `path`, such as the pid file of the service, writable: with
+    /// `RuntimeDirectory=` for a directory under `/run`, and with `ReadWritePaths=` otherwise when
+    /// `ProtectSystem=strict` makes the file system read-only.
+    pub(crate) fn allow_writes_to(&mut self, path: &str) {
+        let path = Path::new(path);
+        match runtime_directory(path) {
+            Some(directory) if self.runtime_directory.is_none() => {
+                self.runtime_directory = Some(directory);
+            }
+            Some(ref directory) if self.runtime_directory.as_ref() == Some(directory) => {}
+            _ if self.effective_protect_system() == Some(ProtectSystem::Strict) => {
+                if let Some(directory) = path.parent() {
+                    let directory = directory.to_string_lossy().into_owned();
+                    if !self.read_write_paths.contains(&directory) {
+                        self.read_write_paths.push(directory);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Adds the hardening directives to the `[Service]` section of `unit`.
+    pub fn apply(&self, unit: &mut UnitFile) {
+        let (protect_system, protect_home) = match self.profile {
+            HardeningProfile::None => (None, None),
+            HardeningProfile::Basic => (Some(ProtectSystem::Full), Some(ProtectHome::ReadOnly)),
+            HardeningProfile::Strict => (Some(ProtectSystem::Strict), Some(ProtectHome::Yes)),
+        };
+        let basic = self.profile != HardeningProfile::None;
+        let strict = self.profile == HardeningProfile::Strict;
+
+        let mut add_bool = |key: &str, value: Option<bool>, preset: bool| {
+            if let Some(value) = value.or(if preset { Some(true) } else { None }) {
+                unit.add("Service", key, if value { "yes" } else { "no" });
+            }
+        };
+
+        add_bool("PrivateTmp", self.private_tmp, basic);
+        add_bool("PrivateDevices", self.private_devices, strict);
+        add_bool("NoNewPrivileges", self.no_new_privileges, basic);
+        add_bool("ProtectKernelTunables", self.protect_kernel, basic);
+        add_bool("ProtectKernelModules", self.protect_kernel, basic);
+        add_bool("ProtectKernelLogs", self.protect_kernel, basic);
+        add_bool("ProtectControlGroups", self.protect_control_groups, basic);
+        add_bool("RestrictSUIDSGID", self.restrict_privileges, strict);
+        add_bool("RestrictRealtime", self.restrict_privileges, strict);
+        add_bool("LockPersonality", self.restrict_privileges, strict);
+
+        if let Some(protect_system) = self.protect_system.or(protect_system) {
+            unit.add("Service", "ProtectSystem", &protect_system.to_string());
+        }
+        if let Some(protect_home) = self.protect_home.or(protect_home) {
+            unit.add("Service", "ProtectHome", &protect_home.to_string());
+        }
+
+        match self.capability_bounding_set {
+            Some(ref capabilities) => {
+                unit.add("Service", "CapabilityBoundingSet", &capabilities.join(" "))
+            }
+            None if strict => unit.add("Service", "CapabilityBoundingSet", ""),
+            None => {}
+        }
+        match self.system_call_filter {
+            Some(ref filter) => unit.add("Service", "SystemCallFilter", &filter.join(" ")),
+            None if strict => {
+                unit.add("Service", "SystemCallArchitectures", "native");
+                unit.add("Service", "SystemCallFilter", "@system-service");
+            }
+            None => {}
+        }
+
+        let quote_paths = |paths: &[String]| {
+            paths
+                .iter()
+                .map(|path| quote_path(path))
+                .collect::<Vec<String>>()
+                .join(" ")
+        };
+        if !self.read_write_paths.is_empty() {
+            unit.add(
+                "Service",
+                "ReadWritePaths",
+                &quote_paths(&self.read_write_paths),
+            );
+        }
+        if !self.read_only_paths.is_empty() {
+            unit.add(
+                "Service",
+                "ReadOnlyPaths",
+                &quote_paths(&self.read_only_paths),
+            );
+        }
+        if let Some(ref directory) = self.state_directory {
+            unit.add("Service", "StateDirectory", directory);
+        }
+        if let Some(ref directory) = self.logs_directory {
+            unit.add("Service", "LogsDirectory", directory);
+        }
+        if let Some(ref directory) = self.runtime_directory {
+            unit.add("Service", "RuntimeDirectory", directory);
+            // Keeps the last exit code of the service when systemd restarts it.
+            unit.add("Service", "RuntimeDirectoryPreserve", "restart");
+        }
+    }
+}
+
+/// Adds the `Restart=` settings matching `policy` to `unit`.
+///
+/// ```rust
+/// use std::time::Duration;
+/// use ceviche::restart::RestartPolicy;
+/// use ceviche::systemd::{add_restart_policy, UnitFile};
+///
+/// let mut policy = RestartPolicy::on_failure();
+/// policy.delay = Duration::from_secs(5);
+/// policy.burst = Some(3);
+/// policy.interval = Duration::from_secs(60);
+///
+/// let mut unit = UnitFile::new();
+/// add_restart_policy(&policy, &mut unit);
+///
+/// assert_eq!(
+///     unit.render(),
+///     "[Service]\nRestart=on-failure\nRestartSec=5\n\n[Unit]\nStartLimitIntervalSec=60\nStartLimitBurst=3\n"
+/// );
+/// ```
+pub fn add_restart_policy(policy: &RestartPolicy, unit: &mut UnitFile) {
+    let restart = match policy.restart {
+        Restart::Never => return,
+        Restart::OnFailure => "on-failure",
+        Restart::Always => "always",
+    };
+
+    unit.add("Service", "Restart", restart);
+    unit.add("Service", "RestartSec", &format_seconds(policy.delay));
+    if let Some(burst) = policy.burst {
+        unit.add(
+            "Unit",
+            "StartLimitIntervalSec",
+            &format_seconds(policy.interval),
+        );
+        unit.add("Unit", "StartLimitBurst", &burst.to_string());
+    }
+}
+
+/// Name of the unit of a dependency, `.service` is appended to names without a unit type.
+///
+/// ```rust
+/// use ceviche::systemd::dependency_unit_name;
+///
+/// assert_eq!(dependency_unit_name("postgresql"), "postgresql.service");
+/// assert_eq!(dependency_unit_name("network-online.target"), "network-online.target");
+/// ```
+pub fn dependency_unit_name(name: &str) -> String {
+    const UNIT_TYPES: [&str; 11] = [
+        "automount",
+        "device",
+        "mount",
+        "path",
+        "scope",
+        "service",
+        "slice",
+        "socket",
+        "swap",
+        "target",
+        "timer",
+    ];
+    match name.rsplit_once('.') {
+        Some((_, unit_type)) if UNIT_TYPES.contains(&unit_type) => name.to_string(),
+        _ => format!("{}.service", name),
+    }
+}
+
+/// Adds the `Requires=`, `Wants=`, `After=` and `Before=` settings matching `dependencies` to `unit`.
+///
+/// ```rust
+/// use ceviche::dependency::Dependency;
+/// use ceviche::systemd::{add_dependencies, UnitFile};
+///
+/// let dependencies = vec![
+///     Dependency::wants("network-online.target"),
+///     Dependency::requires("postgresql"),
+///     Dependency::after("syslog"),
+///     Dependency::before("nginx"),
+/// ];
+///
+/// let mut unit = UnitFile::new();
+/// add_dependencies(&dependencies, &mut unit);
+///
+/// assert_eq!(unit.render(), "\
+/// [Unit]
+/// Wants=network-online.target
+/// After=network-online.target
+/// Requires=postgresql.service
+/// After=postgresql.service
+/// After=syslog.service
+/// Before=nginx.service
+/// ");
+/// ```
+pub fn add_dependencies(dependencies: &[Dependency], unit: &mut UnitFile) {
+    for dependency in dependencies {
+        let name = dependency_unit_name(&dependency.name);
+        match dependency.strength {
+            Some(Strength::Requires) => unit.add("Unit", "Requires", &name),
+            Some(Strength::Wants) => unit.add("Unit", "Wants", &name),
+            None => (),
+        }
+        match dependency.order {
+            Some(Order::After) => unit.add("Unit", "After", &name),
+            Some(Order::Before) => unit.add("Unit", "Before", &name),
+            None => (),
+        }
+    }
+}
+
+fn format_seconds(duration: Duration) -> String {
+    if duration.subsec_millis() == 0 {
+        duration.as_secs().to_string()
+    } else {
+        format!("{}ms", duration.as_millis())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses a quoted `Environment=` assignment the way systemd does: C-style escapes are resolved and
+    /// `%%` is the `%` specifier.
+    fn unquote_environment(value: &str) -> (String, String) {
+        let inner = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .expect("assignment is not quoted");
+        let mut assignment = String::new();
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('n') => assignment.push('\n'),
+                    Some('r') => assignment.push('\r'),
+                    Some('t') => assignment.push('\t'),
+                    Some(c @ ('"' | '\\')) => assignment.push(c),
+                    c => panic!("unexpected escape {:?}", c),
+                },
+                '%' => {
+                    assert_eq!(chars.next(), Some('%'), "unescaped specifier");
+                    assignment.push('%');
+                }
+                '"' => panic!("unescaped quote"),
+                c => assignment.push(c),
+            }
+        }
+        let (name, value) = assignment.split_once('=').expect("missing =");
+        (name.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn environment_round_trip() {
+        let values = [
+            "foo bar",
+            "  leading and trailing  ",
+            r#"say "hi""#,
+            "it's",
+            "$HOME/${PATH}:$$",
+            "100%",
+            "back\\slash \\\" mixed",
+            "multi\nline\ttab\r",
+        ];
+        let mut definition =
+            ServiceDefinition::new("foobar", "FooBar", "FooBar", "/usr/bin/foobar");
+        definition.environment = values
+            .iter()
+            .enumerate()
+            .map(|(index, value)| (format!("VAR_{}", index), value.to_string()))
+            .collect();
+
+        let unit = service_unit(&definition).render();
+        let parsed: Vec<(String, String)> = unit
+            .lines()
+            .filter_map(|line| line.strip_prefix("Environment="))
+            .map(unquote_environment)
+            .collect();
+
+        assert_eq!(parsed, definition.environment);
+    }
+
+    fn restart_unit(restart: Restart, burst: Option<u32>) -> String {
+        let mut policy = RestartPolicy::new(restart);
+        policy.delay = Duration::from_millis(1500);
+        policy.burst = burst;
+        policy.interval = Duration::from_secs(60);
+
+        let mut unit = UnitFile::new();
+        add_restart_policy(&policy, &mut unit);
+        unit.render()
+    }
+
+    #[test]
+    fn restart_never() {
+        assert_eq!(restart_unit(Restart::Never, None), "");
+        assert_eq!(restart_unit(Restart::Never, Some(3)), "");
+    }
+
+    #[test]
+    fn restart_on_failure() {
+        assert_eq!(
+            restart_unit(Restart::OnFailure, None),
+            "[Service]\nRestart=on-failure\nRestartSec=1500ms\n"
+        );
+        assert_eq!(
+            restart_unit(Restart::OnFailure, Some(3)),
+            "[Service]\nRestart=on-failure\nRestartSec=1500ms\n\n\
+             [Unit]\nStartLimitIntervalSec=60\nStartLimitBurst=3\n"
+        );
+    }
+
+    #[test]
+    fn restart_always() {
+        assert_eq!(
+            restart_unit(Restart::Always, None),
+            "[Service]\nRestart=always\nRestartSec=1500ms\n"
+        );
+        assert_eq!(
+            restart_unit(Restart::Always, Some(5)),
+            "[Service]\nRestart=always\nRestartSec=1500ms\n\n\
+             [Unit]\nStartLimitIntervalSec=60\nStartLimitBurst=5\n"
+        );
+    }
+}